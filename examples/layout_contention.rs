@@ -0,0 +1,144 @@
+use mvcompression::{
+    CachePadded, Clock, Compact, Layout, MVCompression, Rng, SystemClock, SystemRng,
+};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+const OPERATIONS_PER_THREAD: usize = 200_000;
+
+fn main() {
+    println!("🧵 MVCompression Layout Contention Analysis");
+    println!("===========================================\n");
+
+    let cores = thread::available_parallelism().map_or(4, |n| n.get());
+    let mut thread_counts = vec![1, 2, 4, 8, 16, 32];
+    thread_counts.retain(|&n| n <= cores.max(4) * 2);
+    println!("Detected {} hardware threads\n", cores);
+
+    // Layout sizes
+    memory_layouts();
+
+    // Many threads hammering one shared instance
+    shared_instance_contention(&thread_counts);
+
+    // One instance per thread, allocated next to each other
+    adjacent_instance_contention(&thread_counts);
+}
+
+fn padded() -> MVCompression<SystemClock, SystemRng, CachePadded> {
+    MVCompression::builder().layout::<CachePadded>().build()
+}
+
+fn memory_layouts() {
+    println!("💾 Memory Layouts");
+    println!("-----------------");
+    println!("• Compact: {} bytes", std::mem::size_of::<MVCompression>());
+    println!(
+        "• CachePadded: {} bytes",
+        std::mem::size_of::<MVCompression<SystemClock, SystemRng, CachePadded>>()
+    );
+    println!();
+}
+
+/// Mixed decisions and updates from every thread on one instance.
+fn run_shared<C, R, L>(mvc: MVCompression<C, R, L>, thread_count: usize) -> f64
+where
+    C: Clock + Send + Sync + 'static,
+    R: Rng + Send + Sync + 'static,
+    L: Layout + Send + Sync + 'static,
+    L::Align: Send + Sync,
+{
+    let mvc = Arc::new(mvc);
+    let start = Instant::now();
+    let handles: Vec<_> = (0..thread_count)
+        .map(|thread_id| {
+            let mvc = Arc::clone(&mvc);
+            thread::spawn(move || work(&mvc, thread_id))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (thread_count * OPERATIONS_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Each thread uses its own instance, but the instances sit in one allocation.
+fn run_adjacent<C, R, L>(instances: Vec<MVCompression<C, R, L>>) -> f64
+where
+    C: Clock + Send + Sync + 'static,
+    R: Rng + Send + Sync + 'static,
+    L: Layout + Send + Sync + 'static,
+    L::Align: Send + Sync,
+{
+    let thread_count = instances.len();
+    let instances = Arc::new(instances);
+    let start = Instant::now();
+    let handles: Vec<_> = (0..thread_count)
+        .map(|thread_id| {
+            let instances = Arc::clone(&instances);
+            thread::spawn(move || work(&instances[thread_id], thread_id))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (thread_count * OPERATIONS_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn work<C: Clock, R: Rng, L: Layout>(mvc: &MVCompression<C, R, L>, thread_id: usize) {
+    for i in 0..OPERATIONS_PER_THREAD {
+        // Alternate compressible and incompressible phases so the score keeps moving.
+        let phase = (thread_id * OPERATIONS_PER_THREAD + i) / 64 % 2;
+        let uncompressed = 1000;
+        let compressed = if phase == 0 { 400 } else { 990 };
+        if !mvc.should_skip_compression(uncompressed) {
+            mvc.update_compression_ratio(compressed, uncompressed);
+        }
+    }
+}
+
+fn shared_instance_contention(thread_counts: &[usize]) {
+    println!("🔄 Shared Instance");
+    println!("------------------");
+    println!(
+        "{:>8} {:>16} {:>16} {:>8}",
+        "threads", "compact ops/s", "padded ops/s", "ratio"
+    );
+    for &thread_count in thread_counts {
+        let compact = run_shared(MVCompression::new(), thread_count);
+        let padded = run_shared(padded(), thread_count);
+        println!(
+            "{:>8} {:>16.0} {:>16.0} {:>7.2}x",
+            thread_count,
+            compact,
+            padded,
+            padded / compact
+        );
+    }
+    println!();
+}
+
+fn adjacent_instance_contention(thread_counts: &[usize]) {
+    println!("🧱 Adjacent Per-Thread Instances (false sharing)");
+    println!("------------------------------------------------");
+    println!(
+        "{:>8} {:>16} {:>16} {:>8}",
+        "threads", "compact ops/s", "padded ops/s", "ratio"
+    );
+    for &thread_count in thread_counts {
+        let compact = run_adjacent::<_, _, Compact>(
+            (0..thread_count).map(|_| MVCompression::new()).collect(),
+        );
+        let padded = run_adjacent((0..thread_count).map(|_| padded()).collect());
+        println!(
+            "{:>8} {:>16.0} {:>16.0} {:>7.2}x",
+            thread_count,
+            compact,
+            padded,
+            padded / compact
+        );
+    }
+    println!();
+    println!("Ratios above 1.00x favor the padded layout at that thread count.");
+}
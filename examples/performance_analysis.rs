@@ -1,4 +1,4 @@
-use mvcompression::MVCompression;
+use mvcompression::{CachePadded, MVCompression};
 use std::sync::Arc;
 use std::thread;
 use std::time::Instant;
@@ -104,11 +104,13 @@ fn memory_analysis() {
     
     let mvc = MVCompression::new();
     let size = std::mem::size_of_val(&mvc);
+    let padded = MVCompression::builder().layout::<CachePadded>().build();
     
     println!("• MVCompression struct size: {} bytes", size);
     println!("• AtomicI32 (compression_value): {} bytes", std::mem::size_of::<std::sync::atomic::AtomicI32>());
-    println!("• AtomicUsize (averages) x2: {} bytes", std::mem::size_of::<std::sync::atomic::AtomicUsize>() * 2);
+    println!("• AtomicU64 (averages) x3: {} bytes", std::mem::size_of::<std::sync::atomic::AtomicU64>() * 3);
     println!("• Total overhead per instance: {} bytes", size);
+    println!("• With CachePadded layout: {} bytes", std::mem::size_of_val(&padded));
     println!("• Memory efficiency: Excellent (no heap allocations)");
     println!();
 }
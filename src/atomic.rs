@@ -0,0 +1,20 @@
+//! Atomic types used by the decision state.
+//!
+//! Every piece of shared state is a flag or an 8-, 32- or 64-bit atomic. On targets with
+//! native 64-bit atomics these are the `core` types. Elsewhere (e.g. 32-bit
+//! microcontrollers) they come from `portable-atomic`, which emulates them with
+//! a lock-based fallback or, with the `critical-section` feature, inside
+//! critical sections.
+
+#[cfg(target_has_atomic = "64")]
+pub(crate) use core::sync::atomic::{AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) use portable_atomic::{AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+// Only the `std`-only sharded state uses flags.
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub(crate) use core::sync::atomic::AtomicBool;
+
+#[cfg(all(feature = "std", not(target_has_atomic = "64")))]
+pub(crate) use portable_atomic::AtomicBool;
@@ -0,0 +1,212 @@
+//! Lock-free fixed-point exponential moving averages.
+//!
+//! The original averages computed `(avg >> 3) * 7 + (sample >> 3)` on plain
+//! integers, which drops the low bits of every sample: blocks smaller than 8
+//! bytes contributed nothing and every average was biased low. Averages are now
+//! kept with [`AVERAGE_FRACTION_BITS`] fractional bits and seeded from their
+//! first sample, so even 64-byte messages are tracked to a fraction of a byte.
+
+use crate::atomic::{AtomicU64, Ordering};
+
+/// Number of fractional bits in a fixed-point average.
+pub const AVERAGE_FRACTION_BITS: u32 = 16;
+
+/// Marker for an average that has not seen any samples.
+const EMPTY: u64 = u64::MAX;
+
+/// Largest sample representable without reaching [`EMPTY`]; larger samples saturate.
+const MAX_SAMPLE: u64 = (EMPTY >> AVERAGE_FRACTION_BITS) - 1;
+
+/// Largest smoothing shift honoured; larger shifts would discard every sample.
+const MAX_SMOOTHING: u32 = 32;
+
+/// An exponential moving average stored in one atomic word.
+#[derive(Debug)]
+pub(crate) struct MovingAverage {
+    value: AtomicU64,
+}
+
+impl MovingAverage {
+    pub(crate) const fn new() -> Self {
+        Self {
+            value: AtomicU64::new(EMPTY),
+        }
+    }
+
+    /// Converts an integer sample into fixed point, saturating.
+    pub(crate) fn to_fixed(sample: usize) -> u64 {
+        (sample as u64).min(MAX_SAMPLE) << AVERAGE_FRACTION_BITS
+    }
+
+    /// Folds a fixed-point sample into the average with a weight of `1 / 2^smoothing`.
+    ///
+    /// The first sample seeds the average directly.
+    pub(crate) fn update(&self, sample: u64, smoothing: u32) {
+        let sample = sample.min(MAX_SAMPLE << AVERAGE_FRACTION_BITS);
+        let smoothing = smoothing.min(MAX_SMOOTHING);
+        loop {
+            let current = self.value.load(Ordering::Relaxed);
+            let new_value = if current == EMPTY {
+                sample
+            } else {
+                // avg + (sample - avg) / 2^smoothing, without leaving u64.
+                current - (current >> smoothing) + (sample >> smoothing)
+            };
+            match self.value.compare_exchange_weak(
+                current,
+                new_value,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Folds `count` fixed-point samples with mean `mean` into the average at once.
+    ///
+    /// Equivalent to `count` calls of [`update`](Self::update) with `mean`, up to
+    /// rounding: the old average keeps a weight of `(1 - 1/2^smoothing)^count`.
+    /// An empty average is seeded with `mean`.
+    pub(crate) fn update_batch(&self, mean: u64, count: u64, smoothing: u32) {
+        if count == 0 {
+            return;
+        }
+        let mean = mean.min(MAX_SAMPLE << AVERAGE_FRACTION_BITS);
+        let keep = retained_weight(smoothing.min(MAX_SMOOTHING), count);
+        loop {
+            let current = self.value.load(Ordering::Relaxed);
+            let new_value = if current == EMPTY {
+                mean
+            } else {
+                let blended =
+                    current as u128 * keep as u128 + mean as u128 * (ONE - keep) as u128;
+                (blended >> WEIGHT_BITS) as u64
+            };
+            match self.value.compare_exchange_weak(
+                current,
+                new_value,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Replaces the average with a single fixed-point sample.
+    pub(crate) fn reset_to(&self, sample: u64) {
+        let sample = sample.min(MAX_SAMPLE << AVERAGE_FRACTION_BITS);
+        self.value.store(sample, Ordering::Relaxed);
+    }
+
+    /// Forgets every sample, so the next one seeds the average again.
+    pub(crate) fn clear(&self) {
+        self.value.store(EMPTY, Ordering::Relaxed);
+    }
+
+    /// Returns the fixed-point average, or `None` before the first sample.
+    pub(crate) fn get(&self) -> Option<u64> {
+        let value = self.value.load(Ordering::Relaxed);
+        (value != EMPTY).then_some(value)
+    }
+
+    /// Returns the average rounded to the nearest integer, or zero before the first sample.
+    pub(crate) fn get_rounded(&self) -> usize {
+        self.get().map_or(0, |value| {
+            let rounded = (value + (1 << (AVERAGE_FRACTION_BITS - 1))) >> AVERAGE_FRACTION_BITS;
+            rounded.min(usize::MAX as u64) as usize
+        })
+    }
+
+    /// Returns the average as a float, or zero before the first sample.
+    pub(crate) fn get_f64(&self) -> f64 {
+        self.get().map_or(0.0, |value| {
+            value as f64 / (1u64 << AVERAGE_FRACTION_BITS) as f64
+        })
+    }
+}
+
+/// Fractional bits of the weights used by batch updates.
+const WEIGHT_BITS: u32 = 32;
+
+/// A weight of one in [`WEIGHT_BITS`] fixed point.
+const ONE: u64 = 1 << WEIGHT_BITS;
+
+/// Returns `(1 - 1/2^smoothing)^count` in [`WEIGHT_BITS`] fixed point.
+fn retained_weight(smoothing: u32, count: u64) -> u64 {
+    let mut base = ONE - (ONE >> smoothing);
+    let mut weight = ONE;
+    let mut count = count;
+    while count > 0 && weight > 0 {
+        if count & 1 == 1 {
+            weight = ((weight as u128 * base as u128) >> WEIGHT_BITS) as u64;
+        }
+        base = ((base as u128 * base as u128) >> WEIGHT_BITS) as u64;
+        count >>= 1;
+    }
+    weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_sample_seeds_average() {
+        let average = MovingAverage::new();
+        assert_eq!(average.get(), None);
+        assert_eq!(average.get_rounded(), 0);
+
+        average.update(MovingAverage::to_fixed(800), 3);
+        assert_eq!(average.get_rounded(), 800);
+    }
+
+    #[test]
+    fn test_small_samples_are_tracked() {
+        let average = MovingAverage::new();
+        average.update(MovingAverage::to_fixed(64), 3);
+        for _ in 0..100 {
+            average.update(MovingAverage::to_fixed(5), 3);
+        }
+        assert_eq!(average.get_rounded(), 5);
+        assert!((average.get_f64() - 5.0).abs() < 0.001);
+
+        average.update(MovingAverage::to_fixed(13), 3);
+        assert!((average.get_f64() - 6.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_batch_update_matches_repeated_updates() {
+        let single = MovingAverage::new();
+        let batched = MovingAverage::new();
+        single.update(MovingAverage::to_fixed(1000), 3);
+        batched.update(MovingAverage::to_fixed(1000), 3);
+
+        for _ in 0..10 {
+            single.update(MovingAverage::to_fixed(200), 3);
+        }
+        batched.update_batch(MovingAverage::to_fixed(200), 10, 3);
+        assert!((single.get_f64() - batched.get_f64()).abs() < 0.01);
+
+        // A batch into an empty average seeds it with the batch mean.
+        let empty = MovingAverage::new();
+        empty.update_batch(MovingAverage::to_fixed(64), 5, 3);
+        assert_eq!(empty.get_rounded(), 64);
+        assert_eq!(retained_weight(0, 1), 0);
+        assert_eq!(retained_weight(3, 0), ONE);
+    }
+
+    #[test]
+    fn test_extreme_samples_saturate() {
+        let average = MovingAverage::new();
+        average.update(MovingAverage::to_fixed(usize::MAX), 3);
+        average.update(MovingAverage::to_fixed(usize::MAX), 3);
+        assert_eq!(average.get_rounded() as u64, MAX_SAMPLE);
+
+        average.update(u64::MAX, 64);
+        assert!(average.get().is_some());
+    }
+}
@@ -0,0 +1,225 @@
+//! Codecs the tool compresses blocks with.
+
+use std::fmt;
+
+/// A block compressor.
+#[derive(Debug)]
+pub enum Codec {
+    /// The built-in LZ77 codec.
+    Lz(Lz),
+    /// DEFLATE at the given level, through `miniz_oxide`.
+    #[cfg(feature = "deflate")]
+    Deflate(u8),
+}
+
+impl Codec {
+    /// Names accepted by [`Codec::parse`].
+    #[cfg(feature = "deflate")]
+    pub const NAMES: &'static str = "lz, deflate";
+    #[cfg(not(feature = "deflate"))]
+    pub const NAMES: &'static str = "lz";
+
+    /// Returns the codec called `name`, or `None` if it is unknown or was not
+    /// compiled in. `level` only applies to DEFLATE.
+    pub fn parse(name: &str, level: u8) -> Option<Self> {
+        match name {
+            "lz" => Some(Codec::Lz(Lz::new())),
+            #[cfg(feature = "deflate")]
+            "deflate" => Some(Codec::Deflate(level.min(10))),
+            _ => {
+                let _ = level;
+                None
+            }
+        }
+    }
+
+    /// Replaces the contents of `out` with `input` compressed.
+    pub fn compress(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Codec::Lz(lz) => lz.compress(input, out),
+            #[cfg(feature = "deflate")]
+            Codec::Deflate(level) => {
+                *out = miniz_oxide::deflate::compress_to_vec(input, *level);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Lz(_) => f.write_str("lz"),
+            #[cfg(feature = "deflate")]
+            Codec::Deflate(level) => write!(f, "deflate:{level}"),
+        }
+    }
+}
+
+/// Shortest match worth encoding.
+const MIN_MATCH: usize = 4;
+/// Bits of the hash table index.
+const HASH_BITS: u32 = 14;
+/// Largest distance a match can refer back.
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+/// A greedy LZ77 compressor in the spirit of LZ4: fast, dependency-free and a
+/// reasonable stand-in for the codecs used in storage engines.
+///
+/// The output is a sequence of tokens. Each holds the number of literals in
+/// its high nibble and the match length minus [`MIN_MATCH`] in its low nibble,
+/// either of which continues in 255-valued bytes when it is 15. The literals
+/// follow the token, then the match offset as a little-endian `u16`. The last
+/// token carries only literals.
+#[derive(Debug)]
+pub struct Lz {
+    /// Position plus one of the last occurrence of each hashed 4-byte word;
+    /// zero for none.
+    table: Vec<u32>,
+}
+
+impl Lz {
+    pub fn new() -> Self {
+        Self {
+            table: vec![0; 1 << HASH_BITS],
+        }
+    }
+
+    /// Replaces the contents of `out` with `input` compressed. Blocks must be
+    /// smaller than 4 GiB.
+    pub fn compress(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        out.clear();
+        self.table.fill(0);
+        let mut anchor = 0;
+        let mut pos = 0;
+        while pos + MIN_MATCH <= input.len() {
+            let word =
+                u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]]);
+            let slot = (word.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+            let candidate = self.table[slot] as usize;
+            self.table[slot] = pos as u32 + 1;
+            if let Some(candidate) = candidate.checked_sub(1) {
+                if pos - candidate <= MAX_OFFSET
+                    && input[candidate..candidate + MIN_MATCH] == input[pos..pos + MIN_MATCH]
+                {
+                    let length = MIN_MATCH
+                        + input[pos + MIN_MATCH..]
+                            .iter()
+                            .zip(&input[candidate + MIN_MATCH..])
+                            .take_while(|(a, b)| a == b)
+                            .count();
+                    emit(out, &input[anchor..pos], Some((pos - candidate, length)));
+                    pos += length;
+                    anchor = pos;
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+        emit(out, &input[anchor..], None);
+    }
+}
+
+/// Appends a token with `literals` and an optional `(offset, length)` match.
+fn emit(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let extra = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | extra.min(15) as u8);
+    if literals.len() >= 15 {
+        push_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if extra >= 15 {
+            push_length(out, extra - 15);
+        }
+    }
+}
+
+fn push_length(out: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        out.push(255);
+        length -= 255;
+    }
+    out.push(length as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_length(input: &[u8], pos: &mut usize, mut length: usize) -> usize {
+        if length == 15 {
+            loop {
+                let byte = input[*pos];
+                *pos += 1;
+                length += usize::from(byte);
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        length
+    }
+
+    fn decompress(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos < input.len() {
+            let token = input[pos];
+            pos += 1;
+            let literals = read_length(input, &mut pos, usize::from(token >> 4));
+            out.extend_from_slice(&input[pos..pos + literals]);
+            pos += literals;
+            if pos == input.len() {
+                break;
+            }
+            let offset = usize::from(u16::from_le_bytes([input[pos], input[pos + 1]]));
+            pos += 2;
+            let length = read_length(input, &mut pos, usize::from(token & 15)) + MIN_MATCH;
+            let start = out.len() - offset;
+            for i in 0..length {
+                out.push(out[start + i]);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_lz_round_trips() {
+        let text = "the quick brown fox jumps over the lazy dog; ".repeat(400);
+        let mut noise = Vec::new();
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        for _ in 0..20_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            noise.push(state as u8);
+        }
+        let mut lz = Lz::new();
+        let mut out = Vec::new();
+        for input in [&b""[..], b"abc", text.as_bytes(), &noise, &vec![7; 70_000]] {
+            lz.compress(input, &mut out);
+            assert_eq!(decompress(&out), input);
+        }
+
+        lz.compress(text.as_bytes(), &mut out);
+        assert!(out.len() * 20 < text.len());
+        lz.compress(&noise, &mut out);
+        assert!(out.len() > noise.len());
+        assert!(out.len() < noise.len() + noise.len() / 100);
+    }
+
+    #[test]
+    fn test_codec_names() {
+        assert_eq!(Codec::parse("lz", 6).unwrap().to_string(), "lz");
+        assert!(Codec::parse("zstd", 6).is_none());
+        #[cfg(feature = "deflate")]
+        {
+            let mut deflate = Codec::parse("deflate", 9).unwrap();
+            assert_eq!(deflate.to_string(), "deflate:9");
+            let mut out = Vec::new();
+            deflate.compress(&[0; 4096], &mut out);
+            assert!(out.len() < 100);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! `mvc`: assesses a dataset before enabling the policy on it.
+//!
+//! Walks the given files and directories, splits every file into blocks,
+//! compresses each block, and runs one adaptive policy over all blocks in walk
+//! order, as a storage engine writing the files one after another would. Every
+//! block is compressed so the ratio of always compressing is known; the policy
+//! is charged the codec CPU time of the blocks it did not skip.
+
+mod codec;
+mod cputime;
+mod report;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use mvcompression::MVCompression;
+
+use codec::Codec;
+use cputime::CpuInstant;
+use report::{Report, Stats};
+
+const USAGE: &str = "\
+usage: mvc [options] <path>...
+
+Splits files into blocks, compresses them and runs the adaptive compression
+policy over them. Directories are walked recursively; symbolic links are not
+followed. Codec times are CPU times of the compressing thread (wall-clock
+times on platforms without a thread CPU clock).
+
+options:
+  -b, --block-size <bytes>  block size, with an optional K or M suffix [64K]
+  -c, --codec <name>        codec to compress with [lz]
+  -l, --level <0-10>        compression level of DEFLATE [6]
+      --json                print the report as JSON
+  -h, --help                print this help
+";
+
+#[derive(Debug)]
+struct Options {
+    block_size: usize,
+    codec: String,
+    level: u8,
+    json: bool,
+    paths: Vec<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            block_size: 64 * 1024,
+            codec: "lz".to_owned(),
+            level: 6,
+            json: false,
+            paths: Vec::new(),
+        }
+    }
+}
+
+/// Parses the arguments after the program name; `Ok(None)` asks for help.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-b" | "--block-size" => options.block_size = parse_size(&value(&arg)?)?,
+            "-c" | "--codec" => options.codec = value(&arg)?,
+            "-l" | "--level" => {
+                let level = value(&arg)?;
+                options.level = level
+                    .parse()
+                    .ok()
+                    .filter(|&level| level <= 10)
+                    .ok_or(format!("invalid level `{level}`"))?;
+            }
+            "--json" => options.json = true,
+            "--" => options.paths.extend(args.by_ref().map(PathBuf::from)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => options.paths.push(PathBuf::from(arg)),
+        }
+    }
+    if options.paths.is_empty() {
+        return Err("no paths given".to_owned());
+    }
+    Ok(Some(options))
+}
+
+/// Parses a block size such as `4096`, `16K` or `1M`.
+fn parse_size(value: &str) -> Result<usize, String> {
+    let (digits, unit) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 1 << 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 1 << 20),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(unit))
+        .filter(|size| (1..=u32::MAX as usize).contains(size))
+        .ok_or(format!("invalid block size `{value}`"))
+}
+
+/// Returns the regular files under `path`, sorted within each directory.
+///
+/// Symbolic links and special files found in directories are skipped; when
+/// `path` itself is one, it is reported as an error.
+fn walk(path: &Path, files: &mut Vec<PathBuf>, errors: &mut Vec<(String, String)>) {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) => return errors.push((path.display().to_string(), error.to_string())),
+    };
+    if !metadata.is_file() && !metadata.is_dir() {
+        let kind = if metadata.is_symlink() {
+            "symbolic link"
+        } else {
+            "special file"
+        };
+        errors.push((path.display().to_string(), format!("{kind}, not read")));
+    } else {
+        walk_entry(path, &metadata, files, errors);
+    }
+}
+
+fn walk_entry(
+    path: &Path,
+    metadata: &fs::Metadata,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<(String, String)>,
+) {
+    if metadata.is_file() {
+        files.push(path.to_owned());
+    } else if metadata.is_dir() {
+        let entries = fs::read_dir(path).and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        });
+        match entries {
+            Ok(mut entries) => {
+                entries.sort();
+                for entry in entries {
+                    match fs::symlink_metadata(&entry) {
+                        Ok(metadata) => walk_entry(&entry, &metadata, files, errors),
+                        Err(error) => errors.push((entry.display().to_string(), error.to_string())),
+                    }
+                }
+            }
+            Err(error) => errors.push((path.display().to_string(), error.to_string())),
+        }
+    }
+}
+
+/// Reads until `buf` is full or the reader is exhausted, returning the number
+/// of bytes read.
+fn read_block(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Runs the blocks of `reader` through `codec` and `policy`.
+fn analyze(
+    reader: &mut impl Read,
+    block_size: usize,
+    codec: &mut Codec,
+    policy: &MVCompression,
+) -> io::Result<Stats> {
+    let mut stats = Stats::default();
+    let mut block = vec![0; block_size];
+    let mut compressed = Vec::new();
+    loop {
+        let len = read_block(reader, &mut block)?;
+        if len == 0 {
+            return Ok(stats);
+        }
+        let skipped = policy.should_skip_compression(len);
+        let start = CpuInstant::now();
+        codec.compress(&block[..len], &mut compressed);
+        let time = start.elapsed();
+        if !skipped {
+            policy.update_compression_ratio(compressed.len(), len);
+        }
+        stats.record(len, compressed.len(), time, skipped);
+    }
+}
+
+fn run(options: Options) -> Result<Report, String> {
+    let mut codec = Codec::parse(&options.codec, options.level).ok_or(format!(
+        "unknown codec `{}` (available: {})",
+        options.codec,
+        Codec::NAMES
+    ))?;
+    let mut report = Report {
+        codec: codec.to_string(),
+        block_size: options.block_size,
+        ..Report::default()
+    };
+    let mut files = Vec::new();
+    for path in &options.paths {
+        walk(path, &mut files, &mut report.errors);
+    }
+
+    let policy = MVCompression::new();
+    for path in files {
+        let name = path.display().to_string();
+        let stats = File::open(&path)
+            .and_then(|mut file| analyze(&mut file, options.block_size, &mut codec, &policy));
+        match stats {
+            Ok(stats) => report.files.push((name, stats)),
+            Err(error) => report.errors.push((name, error.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprint!("mvc: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let json = options.json;
+    match run(options) {
+        Ok(report) => {
+            if json {
+                println!("{}", report.to_json());
+            } else {
+                print!("{report}");
+            }
+            if report.errors.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(error) => {
+            eprintln!("mvc: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<Options>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_arguments() {
+        let options = args(&["-b", "16K", "--json", "data", "--", "-odd"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.block_size, 16 * 1024);
+        assert!(options.json);
+        assert_eq!(
+            options.paths,
+            [PathBuf::from("data"), PathBuf::from("-odd")]
+        );
+        assert!(args(&["--help"]).unwrap().is_none());
+        assert!(args(&[]).is_err());
+        assert!(args(&["-b", "0", "data"]).is_err());
+        assert!(args(&["-l", "11", "data"]).is_err());
+        assert!(args(&["--fast", "data"]).is_err());
+        assert_eq!(parse_size("2m"), Ok(2 << 20));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_reports_named_links() {
+        let dir = std::env::temp_dir().join(format!("mvc-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/data"), b"x").unwrap();
+        std::os::unix::fs::symlink(dir.join("sub/data"), dir.join("link")).unwrap();
+
+        let (mut files, mut errors) = (Vec::new(), Vec::new());
+        walk(&dir, &mut files, &mut errors);
+        walk(&dir.join("link"), &mut files, &mut errors);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The link inside the directory is skipped; the one named is reported.
+        assert_eq!(files, [dir.join("sub/data")]);
+        assert_eq!(
+            errors,
+            [(
+                dir.join("link").display().to_string(),
+                "symbolic link, not read".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_policy_skips_incompressible_runs() {
+        let mut data = "a fairly compressible line of text\n"
+            .repeat(10_000)
+            .into_bytes();
+        let mut state = 1u32;
+        data.extend((0..4 << 20).map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        }));
+        let mut codec = Codec::parse("lz", 0).unwrap();
+        let stats = analyze(&mut &data[..], 4096, &mut codec, &MVCompression::new()).unwrap();
+        assert_eq!(stats.input_bytes, data.len() as u64);
+        assert_eq!(stats.blocks, data.len().div_ceil(4096) as u64);
+        assert!(stats.skip_rate() > 0.5);
+        // Only blocks that would not have shrunk were skipped.
+        assert_eq!(stats.policy_bytes, stats.always_bytes);
+        assert!(stats.policy_time <= stats.always_time);
+    }
+}
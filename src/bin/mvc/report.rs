@@ -0,0 +1,255 @@
+//! Statistics per file and for the whole run, as text or JSON.
+
+use std::fmt::{self, Write as _};
+use std::time::Duration;
+
+use crate::cputime::CpuInstant;
+
+/// What the policy did with the blocks of a file or of the whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    /// Blocks read.
+    pub blocks: u64,
+    /// Blocks the policy skipped.
+    pub skipped: u64,
+    /// Bytes read.
+    pub input_bytes: u64,
+    /// Bytes stored with the policy: skipped blocks as they are, compressed
+    /// blocks at their compressed size unless that is larger.
+    pub policy_bytes: u64,
+    /// Bytes stored if every block were compressed.
+    pub always_bytes: u64,
+    /// CPU time the codec spent on the blocks the policy compressed.
+    pub policy_time: Duration,
+    /// CPU time the codec spent on all blocks.
+    pub always_time: Duration,
+}
+
+impl Stats {
+    /// Counts a block of `input` bytes that compressed to `compressed` bytes
+    /// in `time`.
+    pub fn record(&mut self, input: usize, compressed: usize, time: Duration, skipped: bool) {
+        let stored = compressed.min(input) as u64;
+        self.blocks += 1;
+        self.input_bytes += input as u64;
+        self.always_bytes += stored;
+        self.always_time += time;
+        if skipped {
+            self.skipped += 1;
+            self.policy_bytes += input as u64;
+        } else {
+            self.policy_bytes += stored;
+            self.policy_time += time;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Stats) {
+        self.blocks += other.blocks;
+        self.skipped += other.skipped;
+        self.input_bytes += other.input_bytes;
+        self.policy_bytes += other.policy_bytes;
+        self.always_bytes += other.always_bytes;
+        self.policy_time += other.policy_time;
+        self.always_time += other.always_time;
+    }
+
+    pub fn skip_rate(&self) -> f64 {
+        ratio(self.skipped, self.blocks)
+    }
+
+    /// Stored-to-input ratio with the policy.
+    pub fn policy_ratio(&self) -> f64 {
+        ratio(self.policy_bytes, self.input_bytes)
+    }
+
+    /// Stored-to-input ratio if every block were compressed.
+    pub fn always_ratio(&self) -> f64 {
+        ratio(self.always_bytes, self.input_bytes)
+    }
+
+    /// Fraction of the codec time the policy avoided.
+    pub fn time_saved(&self) -> f64 {
+        let always = self.always_time.as_secs_f64();
+        if always == 0.0 {
+            0.0
+        } else {
+            1.0 - self.policy_time.as_secs_f64() / always
+        }
+    }
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// The statistics of a run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub codec: String,
+    pub block_size: usize,
+    pub files: Vec<(String, Stats)>,
+    /// Paths that could not be read, with the error.
+    pub errors: Vec<(String, String)>,
+}
+
+impl Report {
+    pub fn total(&self) -> Stats {
+        let mut total = Stats::default();
+        for (_, stats) in &self.files {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// Renders the report as one JSON object.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push('{');
+        out.push_str("\"codec\":");
+        push_json_string(&mut out, &self.codec);
+        let _ = write!(out, ",\"block_size\":{},\"files\":[", self.block_size);
+        for (i, (path, stats)) in self.files.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"path\":");
+            push_json_string(&mut out, path);
+            out.push(',');
+            push_json_fields(&mut out, stats);
+            out.push('}');
+        }
+        out.push_str("],\"total\":{");
+        push_json_fields(&mut out, &self.total());
+        out.push_str("},\"errors\":[");
+        for (i, (path, error)) in self.errors.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"path\":");
+            push_json_string(&mut out, path);
+            out.push_str(",\"error\":");
+            push_json_string(&mut out, error);
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+fn push_json_fields(out: &mut String, stats: &Stats) {
+    let _ = write!(
+        out,
+        "\"blocks\":{},\"skipped\":{},\"input_bytes\":{},\"policy_bytes\":{},\
+         \"always_bytes\":{},\"skip_rate\":{:.6},\"policy_ratio\":{:.6},\
+         \"always_ratio\":{:.6},\"policy_cpu_seconds\":{:.6},\"always_cpu_seconds\":{:.6}",
+        stats.blocks,
+        stats.skipped,
+        stats.input_bytes,
+        stats.policy_bytes,
+        stats.always_bytes,
+        stats.skip_rate(),
+        stats.policy_ratio(),
+        stats.always_ratio(),
+        stats.policy_time.as_secs_f64(),
+        stats.always_time.as_secs_f64(),
+    );
+}
+
+fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Renders the report as a table, one row per file and a total.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>8} {:>6} {:>7} {:>7} {:>10} {:>10}  path",
+            "blocks", "skip", "ratio", "always", "cpu ms", "always ms"
+        )?;
+        let row = |f: &mut fmt::Formatter<'_>, stats: &Stats, path: &str| {
+            writeln!(
+                f,
+                "{:>8} {:>5.1}% {:>7.3} {:>7.3} {:>10.1} {:>10.1}  {}",
+                stats.blocks,
+                100.0 * stats.skip_rate(),
+                stats.policy_ratio(),
+                stats.always_ratio(),
+                stats.policy_time.as_secs_f64() * 1e3,
+                stats.always_time.as_secs_f64() * 1e3,
+                path
+            )
+        };
+        for (path, stats) in &self.files {
+            row(f, stats, path)?;
+        }
+        let total = self.total();
+        row(f, &total, "(total)")?;
+        writeln!(
+            f,
+            "codec {}, {}-byte blocks: the policy stores {:.1}% more bytes than always \
+             compressing and saves {:.1}% of the codec {} time",
+            self.codec,
+            self.block_size,
+            100.0 * (ratio(total.policy_bytes, total.always_bytes.max(1)) - 1.0),
+            100.0 * total.time_saved(),
+            if CpuInstant::IS_CPU_TIME {
+                "CPU"
+            } else {
+                "wall-clock"
+            }
+        )?;
+        for (path, error) in &self.errors {
+            writeln!(f, "error: {path}: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_and_json() {
+        let mut stats = Stats::default();
+        stats.record(1000, 300, Duration::from_millis(2), false);
+        stats.record(1000, 1010, Duration::from_millis(2), true);
+        assert_eq!(stats.policy_bytes, 1300);
+        assert_eq!(stats.always_bytes, 1300);
+        assert_eq!(stats.skip_rate(), 0.5);
+        assert_eq!(stats.time_saved(), 0.5);
+
+        let report = Report {
+            codec: "lz".to_owned(),
+            block_size: 1000,
+            files: vec![("a \"b\"\n".to_owned(), stats)],
+            errors: vec![("c".to_owned(), "denied".to_owned())],
+        };
+        assert_eq!(report.total(), stats);
+        let json = report.to_json();
+        assert!(json.starts_with("{\"codec\":\"lz\",\"block_size\":1000,\"files\":[{\"path\":\"a \\\"b\\\"\\n\",\"blocks\":2,"));
+        assert!(json.contains("\"total\":{\"blocks\":2,\"skipped\":1,"));
+        assert!(json.contains(",\"policy_cpu_seconds\":0.002000,\"always_cpu_seconds\":0.004000}"));
+        assert!(json.ends_with(",\"errors\":[{\"path\":\"c\",\"error\":\"denied\"}]}"));
+        assert!(report.to_string().contains("(total)"));
+    }
+}
@@ -0,0 +1,142 @@
+//! Builder for [`MVCompression`] instances with injected dependencies.
+
+use crate::clock::{Clock, DefaultClock};
+use core::marker::PhantomData;
+
+use crate::config::MVCompressionConfig;
+use crate::layout::{Compact, Layout};
+use crate::mvcompression::MVCompression;
+use crate::observer::{NoopObserver, Observer};
+use crate::rng::{Rng, SystemRng};
+#[cfg(feature = "std")]
+use crate::sharded::{ShardedMVCompression, ShardingConfig};
+
+/// Builds an [`MVCompression`] with a custom configuration, clock and random source.
+///
+/// Most callers only need [`MVCompression::new`] or [`MVCompression::with_config`].
+/// The builder exists for callers that inject their own dependencies, most
+/// commonly a [`ManualClock`](crate::ManualClock) and a
+/// [`SeededRng`](crate::SeededRng) in tests.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{DecayConfig, MVCompression, MVCompressionConfig, ManualClock};
+/// use std::time::Duration;
+///
+/// let config = MVCompressionConfig {
+///     decay: Some(DecayConfig::new(Duration::from_secs(1))),
+///     ..MVCompressionConfig::default()
+/// };
+/// let mvc = MVCompression::builder()
+///     .config(config)
+///     .clock(ManualClock::new())
+///     .build();
+///
+/// mvc.clock().advance(Duration::from_secs(1));
+/// assert_eq!(mvc.get_compression_value(), -80);
+/// ```
+#[derive(Debug)]
+pub struct MVCompressionBuilder<C = DefaultClock, R = SystemRng, L = Compact, O = NoopObserver> {
+    config: MVCompressionConfig,
+    clock: C,
+    rng: R,
+    layout: PhantomData<L>,
+    observer: O,
+}
+
+impl MVCompressionBuilder {
+    /// Creates a builder with the default configuration, [`DefaultClock`],
+    /// [`SystemRng`] and no observer.
+    pub const fn new() -> Self {
+        Self {
+            config: MVCompressionConfig::new(),
+            clock: DefaultClock::new(),
+            rng: SystemRng,
+            layout: PhantomData,
+            observer: NoopObserver,
+        }
+    }
+}
+
+impl Default for MVCompressionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock, R: Rng, L: Layout, O: Observer> MVCompressionBuilder<C, R, L, O> {
+    /// Sets the algorithm configuration.
+    pub fn config(mut self, config: MVCompressionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the clock used for time-based behavior.
+    pub fn clock<C2: Clock>(self, clock: C2) -> MVCompressionBuilder<C2, R, L, O> {
+        MVCompressionBuilder {
+            config: self.config,
+            clock,
+            rng: self.rng,
+            layout: PhantomData,
+            observer: self.observer,
+        }
+    }
+
+    /// Replaces the random source used for randomized behavior.
+    pub fn rng<R2: Rng>(self, rng: R2) -> MVCompressionBuilder<C, R2, L, O> {
+        MVCompressionBuilder {
+            config: self.config,
+            clock: self.clock,
+            rng,
+            layout: PhantomData,
+            observer: self.observer,
+        }
+    }
+
+    /// Selects the memory layout of the instance's state (see [`crate::layout`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{CachePadded, MVCompression};
+    ///
+    /// let mvc = MVCompression::builder().layout::<CachePadded>().build();
+    /// assert!(std::mem::size_of_val(&mvc) > std::mem::size_of_val(&MVCompression::new()));
+    /// ```
+    pub fn layout<L2: Layout>(self) -> MVCompressionBuilder<C, R, L2, O> {
+        MVCompressionBuilder {
+            config: self.config,
+            clock: self.clock,
+            rng: self.rng,
+            layout: PhantomData,
+            observer: self.observer,
+        }
+    }
+
+    /// Installs an observer that receives events (see [`crate::observer`]).
+    pub fn observer<O2: Observer>(self, observer: O2) -> MVCompressionBuilder<C, R, L, O2> {
+        MVCompressionBuilder {
+            config: self.config,
+            clock: self.clock,
+            rng: self.rng,
+            layout: PhantomData,
+            observer,
+        }
+    }
+
+    /// Builds the instance.
+    pub fn build(self) -> MVCompression<C, R, L, O> {
+        MVCompression::from_parts(self.config, self.clock, self.rng, self.observer)
+    }
+
+    /// Builds a [`ShardedMVCompression`] whose shards share this configuration
+    /// and clones of the clock, rng and observer.
+    #[cfg(feature = "std")]
+    pub fn build_sharded(self, sharding: ShardingConfig) -> ShardedMVCompression<C, R, L, O>
+    where
+        C: Clone,
+        R: Clone,
+        O: Clone,
+    {
+        ShardedMVCompression::from_parts(self.config, sharding, self.clock, self.rng, self.observer)
+    }
+}
@@ -0,0 +1,154 @@
+//! Time sources for time-based behavior such as score decay.
+//!
+//! [`MVCompression`](crate::MVCompression) never reads the system time directly;
+//! it asks its [`Clock`]. Production code uses `SystemClock`, while tests
+//! inject a [`ManualClock`] and advance it explicitly so every time-dependent
+//! assertion is deterministic.
+//!
+//! Without the `std` feature there is no system time source; the default clock
+//! is then a [`ManualClock`] that the caller advances (or an own [`Clock`] is
+//! injected through the builder).
+
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::{Arc, OnceLock};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::atomic::{AtomicU64, Ordering};
+
+/// The clock used by [`MVCompression::new`](crate::MVCompression::new):
+/// [`SystemClock`] with the `std` feature, [`ManualClock`] without it.
+#[cfg(feature = "std")]
+pub type DefaultClock = SystemClock;
+
+/// The clock used by [`MVCompression::new`](crate::MVCompression::new):
+/// `SystemClock` with the `std` feature, [`ManualClock`] without it.
+#[cfg(not(feature = "std"))]
+pub type DefaultClock = ManualClock;
+
+/// A monotonic time source.
+pub trait Clock {
+    /// Returns the current time in nanoseconds since an arbitrary, fixed epoch.
+    ///
+    /// Successive calls must never go backwards.
+    fn now_nanos(&self) -> u64;
+}
+
+impl<T: Clock + ?Sized> Clock for &T {
+    fn now_nanos(&self) -> u64 {
+        (**self).now_nanos()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clock + ?Sized> Clock for Arc<T> {
+    fn now_nanos(&self) -> u64 {
+        (**self).now_nanos()
+    }
+}
+
+/// The default clock, backed by [`Instant`].
+///
+/// The epoch is the first time any `SystemClock` is read in the process.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    /// Returns the system clock.
+    pub const fn new() -> Self {
+        SystemClock
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now_nanos(&self) -> u64 {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        let elapsed = EPOCH.get_or_init(Instant::now).elapsed();
+        u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX)
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{Clock, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new();
+/// assert_eq!(clock.now_nanos(), 0);
+///
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(clock.now_nanos(), 5_000_000);
+/// ```
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: AtomicU64,
+}
+
+impl ManualClock {
+    /// Creates a clock reading zero.
+    pub const fn new() -> Self {
+        Self {
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Sets the clock to an absolute reading.
+    ///
+    /// Callers are responsible for never moving the clock backwards.
+    pub fn set_nanos(&self, nanos: u64) {
+        self.nanos.store(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_nanos(&self) -> u64 {
+        self.nanos.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_system_clock_is_monotonic() {
+        let clock = SystemClock;
+        let first = clock.now_nanos();
+        let second = clock.now_nanos();
+        assert!(second >= first);
+    }
+
+    #[test]
+    fn test_manual_clock_advance_and_set() {
+        let clock = ManualClock::new();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.now_nanos(), 2_000_000_000);
+
+        clock.set_nanos(5_000_000_000);
+        assert_eq!(clock.now_nanos(), 5_000_000_000);
+
+        // Shared references and Arcs are clocks too.
+        fn read<C: Clock>(clock: C) -> u64 {
+            clock.now_nanos()
+        }
+        assert_eq!(read(&clock), 5_000_000_000);
+        #[cfg(feature = "std")]
+        {
+            let shared = Arc::new(clock);
+            assert_eq!(read(Arc::clone(&shared)), 5_000_000_000);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Tunable parameters for the MVCompression algorithm.
+//!
+//! The defaults reproduce the behavior of the original fixed constants, so
+//! `MVCompression::new()` and `MVCompression::with_config(MVCompressionConfig::default())`
+//! are equivalent.
+
+use core::time::Duration;
+
+use crate::ratio::Ratio;
+use crate::regime::RegimeDetectionConfig;
+use crate::publish::MetricsConfig;
+use crate::trace::TraceConfig;
+use crate::window::Statistics;
+
+/// Compression ratio threshold above which a block is considered poorly compressible.
+/// Blocks with ratio > 9/10 (i.e., compressed size is more than 90% of original)
+/// are treated as non-compressible.
+pub(crate) const BLOCK_COMPRESSABLE_RATIO: Ratio = Ratio::new(9, 10);
+
+/// Largest plausible compressed-to-uncompressed ratio. Codecs expand
+/// incompressible input by a few bytes at most, so anything beyond twice the
+/// input is a bogus report.
+pub(crate) const MAX_EXPANSION_RATIO: Ratio = Ratio::new(2, 1);
+
+/// Bytes allowed on top of [`MAX_EXPANSION_RATIO`] to cover fixed framing
+/// overhead (headers, checksums) on very small blocks.
+pub(crate) const EXPANSION_ALLOWANCE: usize = 64;
+
+/// Weight adjustment for blocks that compress well (ratio ≤ 0.9).
+/// Negative value decreases compression_value, making skipping less likely.
+pub(crate) const COMPRESSIBLE_BLOCK_WEIGHT: i32 = -10;
+
+/// Weight adjustment for blocks that compress poorly (ratio > 0.9).
+/// Positive value increases compression_value, making skipping more likely.
+pub(crate) const NON_COMPRESSIBLE_BLOCK_WEIGHT: i32 = 4;
+
+/// Weight adjustment when compression is skipped.
+/// Negative value provides feedback to eventually retry compression.
+pub(crate) const SKIP_COMPRESSION_BLOCK_WEIGHT: i32 = -1;
+
+/// Initial compression value when algorithm starts.
+/// Negative value ensures compression is attempted initially.
+pub(crate) const INITIAL_COMPRESSION_VALUE: i32 = -80;
+
+/// Maximum allowed compression value.
+/// Prevents the algorithm from becoming permanently skip-heavy.
+pub(crate) const MAX_COMPRESSION_VALUE: i32 = 200;
+
+/// Minimum allowed compression value.
+/// Prevents the algorithm from becoming permanently compression-heavy.
+pub(crate) const MIN_COMPRESSION_VALUE: i32 = -300;
+
+/// Fraction of the expected block size below which blocks are not skipped.
+/// A full ratio extends the skip window down to empty blocks.
+pub(crate) const SKIP_WINDOW_BELOW: Ratio = Ratio::new(1, 1);
+
+/// Fraction of the expected block size above which blocks are not skipped.
+/// Skip if block_size ≤ expected + expected / 4 (within 125% of expected).
+pub(crate) const SKIP_WINDOW_ABOVE: Ratio = Ratio::new(1, 4);
+
+/// Factor by which a block's size must differ from the average to be reported
+/// as an outlier.
+pub(crate) const SIZE_OUTLIER_FACTOR: u32 = 4;
+
+/// Bit shift factor for smoothing in moving average calculation.
+/// Used to divide values: (value >> SMOOTHING_FACTOR) = value / 8
+pub(crate) const SMOOTHING_FACTOR: u32 = 3;
+
+/// Configuration for an [`MVCompression`](crate::MVCompression) instance.
+///
+/// All fields are public so a configuration can be written with struct update
+/// syntax on top of the defaults:
+///
+/// ```rust
+/// use mvcompression::{DecayConfig, MVCompression, MVCompressionConfig};
+/// use std::time::Duration;
+///
+/// let config = MVCompressionConfig {
+///     decay: Some(DecayConfig::new(Duration::from_secs(60))),
+///     ..MVCompressionConfig::default()
+/// };
+/// let mvc = MVCompression::with_config(config);
+/// assert_eq!(mvc.get_compression_value(), -80);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MVCompressionConfig {
+    /// Ratio threshold separating good compression (≤) from poor compression (>).
+    ///
+    /// Blocks are compared against it with exact integer arithmetic.
+    pub compressible_ratio: Ratio,
+    /// Score adjustment applied for a block that compressed well.
+    pub compressible_block_weight: i32,
+    /// Score adjustment applied for a block that compressed poorly.
+    pub non_compressible_block_weight: i32,
+    /// Score adjustment applied every time compression is skipped.
+    pub skip_compression_block_weight: i32,
+    /// Score the algorithm starts from.
+    pub initial_compression_value: i32,
+    /// Upper bound for the score.
+    pub max_compression_value: i32,
+    /// Lower bound for the score.
+    pub min_compression_value: i32,
+    /// Range of block sizes, around the expected size, eligible for skipping.
+    pub skip_window: SkipWindow,
+    /// Score thresholds for entering and leaving the skipping state.
+    pub hysteresis: Hysteresis,
+    /// Bit shift used by the moving averages; each sample carries a weight of `1 / 2^shift`.
+    pub smoothing_factor: u32,
+    /// Optional wall-clock decay of the score. `None` disables decay.
+    pub decay: Option<DecayConfig>,
+    /// Probe rate while skipping: on average one in `probe_rate` skip decisions
+    /// attempts compression instead, so the algorithm keeps sampling the data it
+    /// has stopped compressing. `0` disables probing.
+    pub probe_rate: u32,
+    /// Optional detection of shifts in the ratio distribution. `None` disables detection.
+    pub regime_detection: Option<RegimeDetectionConfig>,
+    /// Statistics that drive classification and the skip window.
+    pub statistics: Statistics,
+    /// Largest compressed-to-uncompressed ratio accepted by
+    /// [`MVCompression::try_update`](crate::MVCompression::try_update).
+    pub max_expansion_ratio: Ratio,
+    /// Bytes accepted on top of `max_expansion_ratio`, so small blocks with
+    /// fixed framing overhead are not rejected.
+    pub expansion_allowance: usize,
+    /// A block more than this many times larger or smaller than the average
+    /// uncompressed size is reported to the [`Observer`](crate::Observer) as a
+    /// size outlier. `0` disables the check.
+    pub size_outlier_factor: u32,
+    /// Whether reported blocks are also counted in histograms of ratios and
+    /// block sizes, returned by `MVCompression::histograms`.
+    /// Requires the `std` feature; ignored without it.
+    pub histograms: bool,
+    /// Whether decisions and accepted results are counted for
+    /// `MVCompression::stats`. Off by default, since counting costs every call
+    /// a write to shared memory.
+    pub counters: bool,
+    /// Events emitted through `tracing` when the `tracing` feature is enabled.
+    pub trace: TraceConfig,
+    /// Metrics published through the `metrics` facade when the `metrics`
+    /// feature is enabled.
+    pub metrics: MetricsConfig,
+}
+
+impl MVCompressionConfig {
+    /// Returns the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            compressible_ratio: BLOCK_COMPRESSABLE_RATIO,
+            compressible_block_weight: COMPRESSIBLE_BLOCK_WEIGHT,
+            non_compressible_block_weight: NON_COMPRESSIBLE_BLOCK_WEIGHT,
+            skip_compression_block_weight: SKIP_COMPRESSION_BLOCK_WEIGHT,
+            initial_compression_value: INITIAL_COMPRESSION_VALUE,
+            max_compression_value: MAX_COMPRESSION_VALUE,
+            min_compression_value: MIN_COMPRESSION_VALUE,
+            skip_window: SkipWindow::new(SKIP_WINDOW_BELOW, SKIP_WINDOW_ABOVE),
+            hysteresis: Hysteresis::NONE,
+            smoothing_factor: SMOOTHING_FACTOR,
+            decay: None,
+            probe_rate: 0,
+            regime_detection: None,
+            statistics: Statistics::MovingAverage,
+            max_expansion_ratio: MAX_EXPANSION_RATIO,
+            expansion_allowance: EXPANSION_ALLOWANCE,
+            size_outlier_factor: SIZE_OUTLIER_FACTOR,
+            histograms: false,
+            counters: false,
+            trace: TraceConfig::new(),
+            metrics: MetricsConfig::new(),
+        }
+    }
+}
+
+impl Default for MVCompressionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Band of block sizes around the expected size in which compression is skipped.
+///
+/// While skipping is enabled, a block of `size` bytes is skipped when
+/// `expected - below * expected <= size <= expected + above * expected`, where
+/// `expected` is the size the skip logic is centered on. Bounds saturate
+/// instead of overflowing. The default (`below = 1`, `above = 1/4`) skips every
+/// block up to 125% of the expected size; blocks much larger than usual are
+/// always compressed.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{MVCompressionConfig, Ratio, SkipWindow};
+///
+/// // Only skip blocks within 25% of the expected size in either direction.
+/// let config = MVCompressionConfig {
+///     skip_window: SkipWindow::symmetric(Ratio::new(1, 4)),
+///     ..MVCompressionConfig::default()
+/// };
+/// assert_eq!(config.skip_window.bounds(1000), (750, 1250));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkipWindow {
+    /// Fraction of the expected size the window extends below it.
+    pub below: Ratio,
+    /// Fraction of the expected size the window extends above it.
+    pub above: Ratio,
+}
+
+impl SkipWindow {
+    /// Creates a skip window extending `below` and `above` the expected size.
+    pub const fn new(below: Ratio, above: Ratio) -> Self {
+        Self { below, above }
+    }
+
+    /// Creates a skip window extending `width` in both directions.
+    pub const fn symmetric(width: Ratio) -> Self {
+        Self::new(width, width)
+    }
+
+    /// Returns the inclusive range of sizes skipped around `expected`, saturating.
+    pub fn bounds(&self, expected: usize) -> (usize, usize) {
+        (
+            expected.saturating_sub(self.below.scale(expected)),
+            expected.saturating_add(self.above.scale(expected)),
+        )
+    }
+
+    /// Returns true if `size` falls inside the window around `expected`.
+    pub fn contains(&self, expected: usize, size: usize) -> bool {
+        let (low, high) = self.bounds(expected);
+        (low..=high).contains(&size)
+    }
+}
+
+impl Default for SkipWindow {
+    fn default() -> Self {
+        Self::new(SKIP_WINDOW_BELOW, SKIP_WINDOW_ABOVE)
+    }
+}
+
+/// Score thresholds for switching between compressing and skipping.
+///
+/// An instance is in one of two [`SkipState`](crate::SkipState)s. While
+/// compressing, it starts skipping once the score rises above `enter_above`;
+/// while skipping, it goes back to compressing once the score falls below
+/// `exit_below`. A gap between the two keeps streams whose score hovers around
+/// a single threshold from flipping block by block. The state is re-evaluated
+/// at every decision. `exit_below` should not exceed `enter_above + 1`, or
+/// scores between the two flip the state at every decision.
+///
+/// The default, [`Hysteresis::NONE`], skips exactly while the score is positive.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{Hysteresis, MVCompressionConfig};
+///
+/// // Enter skip mode above +20, leave it below -20.
+/// let config = MVCompressionConfig {
+///     hysteresis: Hysteresis::symmetric(20),
+///     ..MVCompressionConfig::default()
+/// };
+/// assert_eq!(config.hysteresis, Hysteresis::new(20, -20));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hysteresis {
+    /// Score above which a compressing instance starts skipping.
+    pub enter_above: i32,
+    /// Score below which a skipping instance starts compressing again.
+    pub exit_below: i32,
+}
+
+impl Hysteresis {
+    /// No hysteresis: skip while the score is above zero.
+    pub const NONE: Hysteresis = Hysteresis::new(0, 1);
+
+    /// Creates thresholds for entering and leaving the skipping state.
+    pub const fn new(enter_above: i32, exit_below: i32) -> Self {
+        Self {
+            enter_above,
+            exit_below,
+        }
+    }
+
+    /// Enters skipping above `band` and leaves it below `-band`.
+    pub const fn symmetric(band: i32) -> Self {
+        Self::new(band, band.saturating_neg())
+    }
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Value the score relaxes toward when [`DecayConfig`] is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecayTarget {
+    /// Relax toward [`MVCompressionConfig::initial_compression_value`].
+    #[default]
+    Initial,
+    /// Relax toward zero, the point where skip logic switches on.
+    Zero,
+}
+
+/// Wall-clock decay of the compression score.
+///
+/// Without decay the score only moves when blocks are processed, so a stream
+/// that was idle for an hour resumes with whatever it believed an hour ago.
+/// With decay enabled the distance between the score and its [`DecayTarget`]
+/// halves every `half_life` of elapsed time, as measured by the instance's
+/// [`Clock`](crate::Clock).
+///
+/// Decay is applied lazily at the start of every decision or update. The
+/// score follows the exponential curve, rounded to the nearest integer; time
+/// too short to move it by a whole unit is carried over to the next call, so
+/// frequent calls decay as fast as rare ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecayConfig {
+    /// Time after which half of the distance to the target has been forgotten.
+    pub half_life: Duration,
+    /// Value the score relaxes toward.
+    pub target: DecayTarget,
+}
+
+impl DecayConfig {
+    /// Creates a decay configuration relaxing toward the initial score.
+    pub const fn new(half_life: Duration) -> Self {
+        Self {
+            half_life,
+            target: DecayTarget::Initial,
+        }
+    }
+
+    /// Returns a copy of this configuration relaxing toward `target`.
+    pub const fn with_target(mut self, target: DecayTarget) -> Self {
+        self.target = target;
+        self
+    }
+}
@@ -0,0 +1,38 @@
+//! Skip decisions together with the reason they were made.
+
+/// Why [`MVCompression::decide`](crate::MVCompression::decide) decided as it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecisionReason {
+    /// A [`PolicyMode`](crate::PolicyMode) override pinned the decision.
+    Overridden,
+    /// The instance is in the compressing [`SkipState`](crate::SkipState).
+    Compressing,
+    /// The instance is skipping, but the block is outside the skip window.
+    OutsideWindow,
+    /// The instance is skipping, but the block was chosen as a probe.
+    Probe,
+    /// The block was skipped.
+    Skipped,
+}
+
+/// A skip decision and its reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decision {
+    /// True if compression should be skipped.
+    pub skip: bool,
+    /// Why.
+    pub reason: DecisionReason,
+}
+
+impl Decision {
+    pub(crate) const fn compress(reason: DecisionReason) -> Self {
+        Self {
+            skip: false,
+            reason,
+        }
+    }
+
+    pub(crate) const fn skip(reason: DecisionReason) -> Self {
+        Self { skip: true, reason }
+    }
+}
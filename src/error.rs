@@ -0,0 +1,37 @@
+//! Errors reported when feeding compression results back into the algorithm.
+
+use core::fmt;
+
+/// A compression result rejected by [`MVCompression::try_update`](crate::MVCompression::try_update).
+///
+/// Rejected results leave the learned state untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The uncompressed block was empty, so its ratio is undefined.
+    EmptyBlock,
+    /// The compressed size exceeds what any real codec produces for the block.
+    ///
+    /// The limit is derived from
+    /// [`MVCompressionConfig::max_expansion_ratio`](crate::MVCompressionConfig::max_expansion_ratio)
+    /// and [`MVCompressionConfig::expansion_allowance`](crate::MVCompressionConfig::expansion_allowance).
+    CompressedTooLarge {
+        /// Reported compressed size.
+        compressed: usize,
+        /// Largest compressed size accepted for the block.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::EmptyBlock => f.write_str("uncompressed block is empty"),
+            UpdateError::CompressedTooLarge { compressed, limit } => write!(
+                f,
+                "compressed size {compressed} exceeds the sanity limit of {limit} bytes"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for UpdateError {}
@@ -0,0 +1,534 @@
+//! Prometheus text exposition of policy metrics.
+//!
+//! [`PrometheusExposition`] collects the state of one or more instances, each
+//! with its own labels, and renders it in the Prometheus text format (version
+//! 0.0.4), which OpenMetrics scrapers accept as well. Rendering only produces a
+//! `String`, so the result can be served by whatever HTTP endpoint the
+//! application already has, without tying the crate to a metrics library.
+//!
+//! With the default prefix the following families are rendered, one sample per
+//! instance:
+//!
+//! | Metric | Type | Meaning |
+//! |--------|------|---------|
+//! | `mvcompression_score` | gauge | Compression value |
+//! | `mvcompression_compressed_size_bytes` | gauge | Average compressed block size |
+//! | `mvcompression_uncompressed_size_bytes` | gauge | Average uncompressed block size |
+//! | `mvcompression_ratio` | gauge | Average compression ratio |
+//! | `mvcompression_skipping` | gauge | `1` while in the skipping state |
+//! | `mvcompression_mode` | gauge | `1` for the current `mode` label, `0` for the others |
+//! | `mvcompression_skips_total` | counter | Decisions that skipped compression |
+//! | `mvcompression_attempts_total` | counter | Decisions that compressed |
+//! | `mvcompression_outcomes_total` | counter | Compression results reported |
+//! | `mvcompression_saved_bytes_total` | counter | Bytes saved by compression |
+//! | `mvcompression_skip_transitions_total` | counter | Skip state changes, by `direction` |
+//! | `mvcompression_regime_changes_total` | counter | Detected regime changes |
+//!
+//! The skip, attempt, outcome and saved-bytes counters stay at zero unless
+//! [`MVCompressionConfig::counters`](crate::MVCompressionConfig::counters) is set.
+
+use std::fmt::{self, Write};
+use std::string::String;
+use std::vec::Vec;
+
+use crate::clock::Clock;
+use crate::layout::Layout;
+use crate::mode::{PolicyMode, SkipState};
+use crate::mvcompression::MVCompression;
+use crate::observer::Observer;
+use crate::rng::Rng;
+use crate::sharded::ShardedMVCompression;
+use crate::snapshot::Snapshot;
+use crate::stats::PolicyStats;
+
+/// Default prefix of every metric name.
+pub const DEFAULT_PREFIX: &str = "mvcompression";
+
+/// Metrics of several instances, rendered in the Prometheus text format by
+/// its [`Display`](fmt::Display) implementation.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{MVCompression, MVCompressionConfig, PrometheusExposition};
+///
+/// let config = MVCompressionConfig {
+///     counters: true,
+///     ..MVCompressionConfig::default()
+/// };
+/// let wal = MVCompression::with_config(config);
+/// let blobs = MVCompression::with_config(config);
+/// wal.update_compression_ratio(250, 1000);
+///
+/// let mut exposition = PrometheusExposition::new();
+/// exposition
+///     .add(&[("instance", "wal")], &wal)
+///     .add(&[("instance", "blobs")], &blobs);
+/// let text = exposition.to_string();
+///
+/// assert!(text.contains("mvcompression_score{instance=\"wal\"} -90\n"));
+/// assert!(text.contains("mvcompression_saved_bytes_total{instance=\"wal\"} 750\n"));
+/// assert!(text.contains("mvcompression_score{instance=\"blobs\"} -80\n"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrometheusExposition {
+    prefix: String,
+    instances: Vec<Instance>,
+}
+
+/// A value of the extra label of a family and how to read its sample.
+type Series<'a> = (&'a str, &'a dyn Fn(&Instance) -> f64);
+
+/// The state of one instance and its rendered labels.
+#[derive(Debug, Clone)]
+struct Instance {
+    labels: String,
+    snapshot: Snapshot,
+    stats: PolicyStats,
+    mode: PolicyMode,
+}
+
+impl PrometheusExposition {
+    /// Creates an empty exposition using [`DEFAULT_PREFIX`].
+    pub fn new() -> Self {
+        Self::with_prefix(DEFAULT_PREFIX)
+    }
+
+    /// Creates an empty exposition whose metric names start with `prefix`.
+    ///
+    /// # Panics
+    /// Panics if `prefix` is not a valid Prometheus metric name.
+    pub fn with_prefix(prefix: &str) -> Self {
+        assert!(is_valid_name(prefix), "invalid metric prefix: {prefix:?}");
+        Self {
+            prefix: String::from(prefix),
+            instances: Vec::new(),
+        }
+    }
+
+    /// Adds the current state of `policy`, identified by `labels`.
+    ///
+    /// # Panics
+    /// Panics if a label name is not a valid Prometheus label name. Label
+    /// values are escaped as needed.
+    pub fn add<C: Clock, R: Rng, L: Layout, O: Observer>(
+        &mut self,
+        labels: &[(&str, &str)],
+        policy: &MVCompression<C, R, L, O>,
+    ) -> &mut Self {
+        self.add_state(labels, policy.snapshot(), policy.stats(), policy.mode())
+    }
+
+    /// Adds the global state of a sharded instance, identified by `labels`.
+    ///
+    /// The score and averages are those of [`ShardedMVCompression::snapshot`],
+    /// the counters those of [`ShardedMVCompression::stats`].
+    pub fn add_sharded<C: Clock, R: Rng, L: Layout, O: Observer>(
+        &mut self,
+        labels: &[(&str, &str)],
+        policy: &ShardedMVCompression<C, R, L, O>,
+    ) -> &mut Self {
+        self.add_state(labels, policy.snapshot(), policy.stats(), policy.mode())
+    }
+
+    /// Adds state that was captured elsewhere, e.g. received from another
+    /// process, identified by `labels`.
+    pub fn add_state(
+        &mut self,
+        labels: &[(&str, &str)],
+        snapshot: Snapshot,
+        stats: PolicyStats,
+        mode: PolicyMode,
+    ) -> &mut Self {
+        self.instances.push(Instance {
+            labels: render_labels(labels),
+            snapshot,
+            stats,
+            mode,
+        });
+        self
+    }
+
+    /// Returns the number of instances added.
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Returns true if no instance was added.
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Writes one metric family: its help and type lines and a sample per
+    /// instance.
+    fn family(
+        &self,
+        out: &mut fmt::Formatter<'_>,
+        name: &str,
+        kind: &str,
+        help: &str,
+        value: impl Fn(&Instance) -> f64,
+    ) -> fmt::Result {
+        writeln!(out, "# HELP {}_{name} {help}", self.prefix)?;
+        writeln!(out, "# TYPE {}_{name} {kind}", self.prefix)?;
+        for instance in &self.instances {
+            write!(out, "{}_{name}", self.prefix)?;
+            if !instance.labels.is_empty() {
+                write!(out, "{{{}}}", instance.labels)?;
+            }
+            writeln!(out, " {}", value(instance))?;
+        }
+        Ok(())
+    }
+
+    /// Writes a metric family with one extra label, one sample per value of it.
+    fn labeled_family(
+        &self,
+        out: &mut fmt::Formatter<'_>,
+        name: &str,
+        kind: &str,
+        help: &str,
+        label: &str,
+        series: &[Series<'_>],
+    ) -> fmt::Result {
+        writeln!(out, "# HELP {}_{name} {help}", self.prefix)?;
+        writeln!(out, "# TYPE {}_{name} {kind}", self.prefix)?;
+        for instance in &self.instances {
+            let separator = if instance.labels.is_empty() { "" } else { "," };
+            for (label_value, value) in series {
+                writeln!(
+                    out,
+                    "{}_{name}{{{}{separator}{label}=\"{label_value}\"}} {}",
+                    self.prefix,
+                    instance.labels,
+                    value(instance)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PrometheusExposition {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for PrometheusExposition {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.family(
+            out,
+            "score",
+            "gauge",
+            "Compression value; positive values enable skipping.",
+            |i| f64::from(i.snapshot.compression_value),
+        )?;
+        self.family(
+            out,
+            "compressed_size_bytes",
+            "gauge",
+            "Moving average of compressed block sizes.",
+            |i| i.snapshot.compressed_average,
+        )?;
+        self.family(
+            out,
+            "uncompressed_size_bytes",
+            "gauge",
+            "Moving average of uncompressed block sizes.",
+            |i| i.snapshot.uncompressed_average,
+        )?;
+        self.family(
+            out,
+            "ratio",
+            "gauge",
+            "Moving average of per-block compression ratios.",
+            |i| i.snapshot.ratio_average,
+        )?;
+        self.family(
+            out,
+            "skipping",
+            "gauge",
+            "1 while adaptive decisions skip compression.",
+            |i| flag(i.stats.skip_state == SkipState::Skipping),
+        )?;
+        self.labeled_family(
+            out,
+            "mode",
+            "gauge",
+            "Operator override; 1 for the current mode.",
+            "mode",
+            &[
+                ("adaptive", &|i| flag(i.mode == PolicyMode::Adaptive)),
+                ("always_compress", &|i| {
+                    flag(matches!(i.mode, PolicyMode::AlwaysCompress { .. }))
+                }),
+                ("never_compress", &|i| {
+                    flag(matches!(i.mode, PolicyMode::NeverCompress { .. }))
+                }),
+            ],
+        )?;
+        self.family(
+            out,
+            "skips_total",
+            "counter",
+            "Decisions that skipped compression.",
+            |i| i.stats.skipped as f64,
+        )?;
+        self.family(
+            out,
+            "attempts_total",
+            "counter",
+            "Decisions that compressed.",
+            |i| i.stats.attempted as f64,
+        )?;
+        self.family(
+            out,
+            "outcomes_total",
+            "counter",
+            "Compression results reported.",
+            |i| i.stats.outcomes as f64,
+        )?;
+        self.family(
+            out,
+            "saved_bytes_total",
+            "counter",
+            "Bytes saved by compression.",
+            |i| i.stats.bytes_saved as f64,
+        )?;
+        self.labeled_family(
+            out,
+            "skip_transitions_total",
+            "counter",
+            "Skip state changes.",
+            "direction",
+            &[
+                ("started", &|i| i.stats.skip_entered as f64),
+                ("stopped", &|i| i.stats.skip_exited as f64),
+            ],
+        )?;
+        self.family(
+            out,
+            "regime_changes_total",
+            "counter",
+            "Detected regime changes.",
+            |i| i.snapshot.regime_changes as f64,
+        )
+    }
+}
+
+/// Renders the metrics of `policies`, each labeled `instance="<name>"`.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{exposition, MVCompression, MVCompressionConfig};
+///
+/// let mvc = MVCompression::with_config(MVCompressionConfig {
+///     counters: true,
+///     ..MVCompressionConfig::default()
+/// });
+/// mvc.should_skip_compression(1000);
+///
+/// let text = exposition::render([("default", &mvc)]);
+/// assert!(text.contains("mvcompression_attempts_total{instance=\"default\"} 1\n"));
+/// ```
+pub fn render<'a, C, R, L, O, I>(policies: I) -> String
+where
+    C: Clock + 'a,
+    R: Rng + 'a,
+    L: Layout + 'a,
+    O: Observer + 'a,
+    I: IntoIterator<Item = (&'a str, &'a MVCompression<C, R, L, O>)>,
+{
+    let mut exposition = PrometheusExposition::new();
+    for (name, policy) in policies {
+        exposition.add(&[("instance", name)], policy);
+    }
+    exposition.to_string()
+}
+
+fn flag(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Renders `name="value"` pairs separated by commas, escaping the values.
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    let mut rendered = String::new();
+    for (index, (name, value)) in labels.iter().enumerate() {
+        assert!(is_valid_name(name), "invalid label name: {name:?}");
+        if index > 0 {
+            rendered.push(',');
+        }
+        let _ = write!(rendered, "{name}=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => rendered.push_str("\\\\"),
+                '"' => rendered.push_str("\\\""),
+                '\n' => rendered.push_str("\\n"),
+                c => rendered.push(c),
+            }
+        }
+        rendered.push('"');
+    }
+    rendered
+}
+
+/// Returns true for names matching `[a-zA-Z_][a-zA-Z0-9_]*`, which are valid
+/// both as metric and as label names.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MVCompressionConfig;
+    use crate::sharded::ShardingConfig;
+
+    const COUNTING: MVCompressionConfig = MVCompressionConfig {
+        counters: true,
+        ..MVCompressionConfig::new()
+    };
+
+    /// Returns the value of the sample with exactly this name and label set.
+    fn scrape(text: &str, series: &str) -> Option<f64> {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .map(|value| value.parse().unwrap())
+    }
+
+    #[test]
+    fn test_renders_state_of_each_instance() {
+        let busy = MVCompression::with_config(COUNTING);
+        let idle = MVCompression::with_config(COUNTING);
+        busy.should_skip_compression(1000);
+        busy.update_compression_ratio(250, 1000);
+        busy.update_compression_ratio(1100, 1000);
+        busy.force_value(10);
+        busy.should_skip_compression(1000);
+
+        let text = render([("busy", &busy), ("idle", &idle)]);
+        let busy_label = "{instance=\"busy\"}";
+        assert_eq!(
+            scrape(&text, &format!("mvcompression_score{busy_label}")),
+            Some(9.0)
+        );
+        assert_eq!(
+            scrape(&text, &format!("mvcompression_skips_total{busy_label}")),
+            Some(1.0)
+        );
+        assert_eq!(
+            scrape(&text, &format!("mvcompression_attempts_total{busy_label}")),
+            Some(1.0)
+        );
+        assert_eq!(
+            scrape(&text, &format!("mvcompression_outcomes_total{busy_label}")),
+            Some(2.0)
+        );
+        assert_eq!(
+            scrape(
+                &text,
+                &format!("mvcompression_saved_bytes_total{busy_label}")
+            ),
+            Some(750.0)
+        );
+        assert_eq!(
+            scrape(&text, &format!("mvcompression_skipping{busy_label}")),
+            Some(1.0)
+        );
+        assert_eq!(
+            scrape(
+                &text,
+                "mvcompression_skip_transitions_total{instance=\"busy\",direction=\"started\"}"
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            scrape(&text, "mvcompression_score{instance=\"idle\"}"),
+            Some(-80.0)
+        );
+        assert_eq!(
+            scrape(&text, "mvcompression_ratio{instance=\"idle\"}"),
+            Some(0.0)
+        );
+
+        // Every family is announced once, followed by one sample per instance.
+        assert_eq!(
+            text.matches("# TYPE mvcompression_score gauge\n").count(),
+            1
+        );
+        assert_eq!(text.matches("# TYPE ").count(), 12);
+        assert_eq!(
+            text.lines().filter(|line| !line.starts_with('#')).count(),
+            2 * 15
+        );
+    }
+
+    #[test]
+    fn test_mode_is_a_state_set() {
+        let mvc = MVCompression::new();
+        mvc.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: true,
+        });
+        let mut exposition = PrometheusExposition::with_prefix("wal");
+        exposition.add(&[], &mvc);
+        let text = exposition.to_string();
+
+        assert_eq!(scrape(&text, "wal_mode{mode=\"adaptive\"}"), Some(0.0));
+        assert_eq!(
+            scrape(&text, "wal_mode{mode=\"never_compress\"}"),
+            Some(1.0)
+        );
+        assert_eq!(scrape(&text, "wal_score"), Some(-80.0));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        let mut exposition = PrometheusExposition::new();
+        exposition.add(
+            &[("path", "C:\\data \"x\"\n"), ("tier", "hot")],
+            &MVCompression::new(),
+        );
+        let text = exposition.to_string();
+        assert!(text
+            .contains("mvcompression_score{path=\"C:\\\\data \\\"x\\\"\\n\",tier=\"hot\"} -80\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid label name")]
+    fn test_invalid_label_name_panics() {
+        PrometheusExposition::new().add(&[("bad-name", "x")], &MVCompression::new());
+    }
+
+    #[test]
+    fn test_sharded_counters_are_summed() {
+        let sharded = MVCompression::builder()
+            .config(COUNTING)
+            .build_sharded(ShardingConfig::with_shards(2));
+        sharded.try_update_on(0, 500, 1000).unwrap();
+        sharded.try_update_on(1, 900, 1000).unwrap();
+        let mut exposition = PrometheusExposition::new();
+        exposition.add_sharded(&[("instance", "sharded")], &sharded);
+        let text = exposition.to_string();
+
+        assert_eq!(
+            scrape(&text, "mvcompression_outcomes_total{instance=\"sharded\"}"),
+            Some(2.0)
+        );
+        assert_eq!(
+            scrape(
+                &text,
+                "mvcompression_saved_bytes_total{instance=\"sharded\"}"
+            ),
+            Some(600.0)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Log-bucketed histograms of compression ratios and block sizes.
+//!
+//! The moving averages summarize a stream with one number each, which hides
+//! bimodal data: a stream where 40% of blocks compress to 0.3 and 60% do not
+//! compress at all averages to a ratio of 0.72. With
+//! [`MVCompressionConfig::histograms`](crate::MVCompressionConfig::histograms)
+//! enabled, every reported block is also counted in lock-free histograms of its
+//! ratio and uncompressed size, which [`MVCompression::histograms`] returns for
+//! percentile queries.
+//!
+//! Buckets are logarithmic with [`SUB_BUCKETS`] linear sub-buckets per power of
+//! two, so every value is placed within 12.5% of its true magnitude. The
+//! buckets are allocated on the first recorded block.
+//!
+//! [`MVCompression::histograms`]: crate::MVCompression::histograms
+
+use std::boxed::Box;
+use std::vec::Vec;
+
+use crate::atomic::{AtomicU64, Ordering};
+use crate::ratio::{fixed_ratio, RATIO_FRACTION_BITS};
+
+/// Number of bits selecting the sub-bucket within a power of two.
+const SUB_BUCKET_BITS: u32 = 3;
+
+/// Number of linear sub-buckets per power of two.
+pub const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+/// Buckets needed for values up to `2^bits - 1`.
+const fn bucket_count(bits: u32) -> usize {
+    (bits - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS
+}
+
+/// Fixed-point ratios saturate at `u32::MAX`.
+const RATIO_BUCKETS: usize = bucket_count(32);
+const SIZE_BUCKETS: usize = bucket_count(64);
+
+/// Returns the bucket holding `value`.
+///
+/// Values below [`SUB_BUCKETS`] have a bucket each; above, every power of two
+/// is split into [`SUB_BUCKETS`] equal parts.
+fn bucket_of(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) as usize & (SUB_BUCKETS - 1);
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+/// Returns the smallest and largest value of `bucket`.
+fn bucket_bounds(bucket: usize) -> (u64, u64) {
+    if bucket < SUB_BUCKETS {
+        return (bucket as u64, bucket as u64);
+    }
+    let shift = (bucket / SUB_BUCKETS - 1) as u32;
+    let low = ((SUB_BUCKETS + bucket % SUB_BUCKETS) as u64) << shift;
+    (low, low + ((1u64 << shift) - 1))
+}
+
+/// Bucket counts that many threads can increment concurrently.
+#[derive(Debug)]
+struct AtomicBuckets<const N: usize>([AtomicU64; N]);
+
+impl<const N: usize> AtomicBuckets<N> {
+    fn new() -> Self {
+        Self([const { AtomicU64::new(0) }; N])
+    }
+
+    fn record(&self, value: u64) {
+        self.0[bucket_of(value)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        for bucket in &self.0 {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn load(&self, scale: f64) -> Histogram {
+        Histogram {
+            counts: self
+                .0
+                .iter()
+                .map(|bucket| bucket.load(Ordering::Relaxed))
+                .collect(),
+            scale,
+        }
+    }
+}
+
+/// The histograms of an instance, allocated on the first recorded block.
+#[derive(Debug)]
+pub(crate) struct AtomicHistograms {
+    ratio: AtomicBuckets<RATIO_BUCKETS>,
+    block_size: AtomicBuckets<SIZE_BUCKETS>,
+}
+
+impl AtomicHistograms {
+    pub(crate) fn new() -> Box<Self> {
+        Box::new(Self {
+            ratio: AtomicBuckets::new(),
+            block_size: AtomicBuckets::new(),
+        })
+    }
+
+    /// Counts a block with a non-zero uncompressed size.
+    pub(crate) fn record(&self, compressed: usize, uncompressed: usize) {
+        if let Some(ratio) = fixed_ratio(compressed, uncompressed) {
+            self.ratio.record(ratio);
+        }
+        self.block_size.record(uncompressed as u64);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.ratio.clear();
+        self.block_size.clear();
+    }
+
+    pub(crate) fn load(&self) -> Histograms {
+        Histograms {
+            ratio: self.ratio.load((1u64 << RATIO_FRACTION_BITS) as f64),
+            block_size: self.block_size.load(1.0),
+        }
+    }
+}
+
+/// Histograms of the blocks reported to an instance, returned by
+/// [`MVCompression::histograms`](crate::MVCompression::histograms).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histograms {
+    /// Compressed-to-uncompressed ratios.
+    pub ratio: Histogram,
+    /// Uncompressed block sizes, in bytes.
+    pub block_size: Histogram,
+}
+
+impl Histograms {
+    /// Adds the counts of `other`, e.g. another shard's.
+    pub fn merge(&mut self, other: &Histograms) {
+        self.ratio.merge(&other.ratio);
+        self.block_size.merge(&other.block_size);
+    }
+}
+
+/// A copy of a log-bucketed histogram.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{MVCompression, MVCompressionConfig};
+///
+/// let mvc = MVCompression::with_config(MVCompressionConfig {
+///     histograms: true,
+///     ..MVCompressionConfig::default()
+/// });
+/// for _ in 0..4 {
+///     mvc.update_compression_ratio(300, 1000);
+/// }
+/// for _ in 0..6 {
+///     mvc.update_compression_ratio(1000, 1000);
+/// }
+///
+/// let ratio = mvc.histograms().unwrap().ratio;
+/// assert_eq!(ratio.count(), 10);
+/// assert!((ratio.fraction_below(0.5) - 0.4).abs() < 1e-9);
+/// assert!((ratio.quantile(0.25).unwrap() - 0.3).abs() < 0.02);
+/// assert!((ratio.quantile(0.5).unwrap() - 1.0).abs() < 0.07);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    /// Raw values per unit of the reported values.
+    scale: f64,
+}
+
+/// One bucket of a [`Histogram`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistogramBucket {
+    /// Smallest value counted in the bucket.
+    pub low: f64,
+    /// Smallest value counted in the next bucket.
+    pub high: f64,
+    /// Number of values in the bucket.
+    pub count: u64,
+}
+
+impl Histogram {
+    /// Returns the number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the value below which a fraction `q` of the recorded values lie
+    /// (nearest rank), or `None` if nothing was recorded.
+    ///
+    /// The result is the middle of the bucket holding that rank. `q` is clamped
+    /// to `0.0..=1.0`.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        let bucket = self.counts.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        let (low, high) = bucket_bounds(bucket);
+        Some((low as f64 + (high - low) as f64 / 2.0) / self.scale)
+    }
+
+    /// Returns the fraction of recorded values below `value`, interpolating
+    /// linearly within the bucket holding `value`. Returns `0.0` if nothing was
+    /// recorded.
+    pub fn fraction_below(&self, value: f64) -> f64 {
+        let total = self.count();
+        if total == 0 {
+            return 0.0;
+        }
+        let below: f64 = self
+            .buckets()
+            .map(|bucket| {
+                if bucket.high <= value {
+                    bucket.count as f64
+                } else if bucket.low < value {
+                    bucket.count as f64 * (value - bucket.low) / (bucket.high - bucket.low)
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+        below / total as f64
+    }
+
+    /// Returns the non-empty buckets in ascending order.
+    pub fn buckets(&self) -> impl Iterator<Item = HistogramBucket> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(bucket, &count)| {
+                let (low, high) = bucket_bounds(bucket);
+                HistogramBucket {
+                    low: low as f64 / self.scale,
+                    high: (high as f64 + 1.0) / self.scale,
+                    count,
+                }
+            })
+    }
+
+    /// Adds the counts of `other`, which must describe the same quantity.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_are_contiguous() {
+        let mut next = 0;
+        for bucket in 0..SIZE_BUCKETS {
+            let (low, high) = bucket_bounds(bucket);
+            assert_eq!(low, next, "bucket {bucket}");
+            assert_eq!(bucket_of(low), bucket);
+            assert_eq!(bucket_of(high), bucket);
+            next = high.wrapping_add(1);
+        }
+        assert_eq!(next, 0, "the last bucket ends at u64::MAX");
+        assert_eq!(bucket_of(u64::from(u32::MAX)), RATIO_BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_bucket_width_is_bounded() {
+        for value in [9u64, 100, 1000, 4096, 65_535, 1 << 40] {
+            let (low, high) = bucket_bounds(bucket_of(value));
+            assert!(((high - low + 1) as f64) / (low as f64) <= 1.0 / SUB_BUCKETS as f64);
+        }
+    }
+
+    #[test]
+    fn test_quantiles_and_fractions() {
+        let histograms = AtomicHistograms::new();
+        for size in [100, 200, 300, 400, 4000] {
+            histograms.record(size / 2, size);
+        }
+        let Histograms { ratio, block_size } = histograms.load();
+        assert_eq!(block_size.count(), 5);
+        let min = block_size.quantile(0.0).unwrap();
+        assert!((96.0..=104.0).contains(&min), "{min}");
+        let median = block_size.quantile(0.5).unwrap();
+        assert!((288.0..=320.0).contains(&median), "{median}");
+        assert!(block_size.quantile(1.0).unwrap() >= 3584.0);
+        assert_eq!(block_size.fraction_below(1000.0), 0.8);
+        assert!((ratio.quantile(0.9).unwrap() - 0.5).abs() < 0.5 / SUB_BUCKETS as f64);
+        assert_eq!(ratio.buckets().count(), 1);
+
+        let mut merged = histograms.load();
+        merged.merge(&histograms.load());
+        assert_eq!(merged.block_size.count(), 10);
+
+        histograms.clear();
+        assert_eq!(histograms.load().ratio.quantile(0.5), None);
+    }
+}
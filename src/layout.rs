@@ -0,0 +1,127 @@
+//! Memory layouts for the shared decision state.
+//!
+//! With the default [`Compact`] layout all of an instance's atomics sit next to
+//! each other, which is the smallest layout and ideal for a handful of threads.
+//! Under heavy multi-writer contention every update of one atomic invalidates
+//! the cache line holding the others, so readers of the compression value and
+//! writers of the averages slow each other down. [`CachePadded`] places every
+//! independently written piece of state on its own cache line, trading memory
+//! for fewer cache-line transfers.
+//!
+//! The layout is a type parameter of [`MVCompression`](crate::MVCompression),
+//! selected through [`MVCompressionBuilder::layout`](crate::MVCompressionBuilder::layout).
+//! `examples/layout_contention.rs` compares both layouts on the local machine.
+
+use core::fmt;
+use core::ops::Deref;
+
+/// A memory layout for the decision state.
+///
+/// Implemented by [`Compact`] and [`CachePadded`].
+pub trait Layout {
+    /// Zero-sized type whose alignment every piece of state is padded to.
+    type Align;
+}
+
+/// Packs the decision state as tightly as possible (the default).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Compact;
+
+impl Layout for Compact {
+    type Align = ();
+}
+
+/// Gives every independently written piece of state its own cache line.
+///
+/// Lines are assumed to be 128 bytes on x86_64, aarch64 and powerpc64 (where
+/// adjacent lines are prefetched in pairs) and 64 bytes elsewhere.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CachePadded;
+
+impl Layout for CachePadded {
+    type Align = CacheLine;
+}
+
+/// Zero-sized marker aligned to a cache line.
+#[cfg_attr(
+    any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "powerpc64"
+    ),
+    repr(align(128))
+)]
+#[cfg_attr(
+    not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        target_arch = "powerpc64"
+    )),
+    repr(align(64))
+)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheLine;
+
+/// A value aligned (and therefore padded) according to layout `L`.
+///
+/// The zero-length array takes no space but raises the alignment of the slot,
+/// and with it the size, to that of `L::Align`.
+pub(crate) struct Slot<T, L: Layout> {
+    _align: [L::Align; 0],
+    value: T,
+}
+
+impl<T, L: Layout> Slot<T, L> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self { _align: [], value }
+    }
+}
+
+impl<T, L: Layout> Deref for Slot<T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: fmt::Debug, L: Layout> fmt::Debug for Slot<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atomic::AtomicU64;
+    use core::mem::{align_of, size_of};
+
+    #[test]
+    fn test_compact_slots_add_no_padding() {
+        assert_eq!(
+            size_of::<Slot<AtomicU64, Compact>>(),
+            size_of::<AtomicU64>()
+        );
+        assert_eq!(
+            align_of::<Slot<AtomicU64, Compact>>(),
+            align_of::<AtomicU64>()
+        );
+    }
+
+    #[test]
+    fn test_padded_slots_fill_a_cache_line() {
+        let line = align_of::<CacheLine>();
+        assert!(line >= 64);
+        assert_eq!(size_of::<Slot<AtomicU64, CachePadded>>(), line);
+
+        // Adjacent padded slots never share a line.
+        let slots = [
+            Slot::<AtomicU64, CachePadded>::new(AtomicU64::new(1)),
+            Slot::<AtomicU64, CachePadded>::new(AtomicU64::new(2)),
+        ];
+        let first = &*slots[0] as *const AtomicU64 as usize;
+        let second = &*slots[1] as *const AtomicU64 as usize;
+        assert_eq!(second - first, line);
+    }
+}
@@ -27,10 +27,12 @@
 //! 
 //! 2. **Skip Logic**: When compression value becomes positive:
 //!    - Compares incoming block size to historical average
-//!    - Skips compression if size is within 25% of expected size
+//!    - Skips compression if size is at most 25% above expected size
+//!      (the band is configurable through [`SkipWindow`])
 //! 
-//! 3. **Moving Averages**: Tracks compressed and uncompressed block sizes
+//! 3. **Moving Averages**: Tracks compressed and uncompressed block sizes and ratios
 //!    - Uses exponential moving average with smoothing factor
+//!    - Kept in fixed point with fractional bits, so small blocks are tracked exactly
 //!    - Helps predict future compression effectiveness
 //! 
 //! ## Example Usage
@@ -94,24 +96,228 @@
 //! 
 //! The algorithm uses several tunable constants that affect its behavior:
 //! 
-//! - `BLOCK_COMPRESSABLE_RATIO`: 0.9 (threshold for good vs poor compression)
+//! - `BLOCK_COMPRESSABLE_RATIO`: 9/10 (threshold for good vs poor compression)
 //! - `INITIAL_COMPRESSION_VALUE`: -80 (starting compression value)
 //! - `COMPRESSIBLE_BLOCK_WEIGHT`: -10 (adjustment for good compression)
 //! - `NON_COMPRESSIBLE_BLOCK_WEIGHT`: 4 (adjustment for poor compression)
 //! - `SKIP_COMPRESSION_BLOCK_WEIGHT`: -1 (adjustment when skipping)
 //! 
+//! Every parameter can be overridden per instance through `MVCompressionConfig`.
+//! 
 //! These parameters create a system that:
 //! - Starts optimistic (negative value = always compress)
 //! - Quickly adapts to poor compression (small positive weight vs large negative)
 //! - Gradually returns to compression attempts through skip penalties
 //! 
+//! ## Score Decay
+//! 
+//! Optionally, the compression value can relax toward its initial value (or zero)
+//! over wall-clock time, so a stream that was idle for a long time does not resume
+//! with stale beliefs. Time is read through the `Clock` trait; tests inject a
+//! `ManualClock` to make decay deterministic.
+//! 
+//! ```rust
+//! use mvcompression::{DecayConfig, MVCompression, MVCompressionConfig, ManualClock};
+//! use std::time::Duration;
+//! 
+//! let config = MVCompressionConfig {
+//!     decay: Some(DecayConfig::new(Duration::from_secs(60))),
+//!     ..MVCompressionConfig::default()
+//! };
+//! let mvc = MVCompression::builder()
+//!     .config(config)
+//!     .clock(ManualClock::new())
+//!     .build();
+//! 
+//! for _ in 0..40 {
+//!     mvc.update_compression_ratio(1000, 1000); // Incompressible data
+//! }
+//! assert_eq!(mvc.get_compression_value(), 80);
+//! 
+//! // One idle half-life later, half of the learned bias is gone.
+//! mvc.clock().advance(Duration::from_secs(60));
+//! assert!(!mvc.should_skip_compression(1000));
+//! assert_eq!(mvc.get_compression_value(), 0);
+//! ```
+//! 
+//! ## Robust Statistics
+//! 
+//! The moving averages can be replaced by a lock-free sliding window over the
+//! last outcomes (`Statistics::Window`). Blocks are then classified by the mean,
+//! median or 90th percentile ratio of the window, and the skip logic compares
+//! incoming blocks against the same statistic of recent block sizes, so single
+//! outliers no longer steer decisions.
+//! 
+//! ```rust
+//! use mvcompression::{MVCompression, MVCompressionConfig, Statistics, WindowStatistic};
+//! 
+//! let mvc = MVCompression::with_config(MVCompressionConfig {
+//!     statistics: Statistics::window(WindowStatistic::Median),
+//!     ..MVCompressionConfig::default()
+//! });
+//! 
+//! for _ in 0..10 {
+//!     mvc.update_compression_ratio(500, 1000);
+//! }
+//! mvc.update_compression_ratio(1000, 1000); // One outlier
+//! 
+//! let stats = mvc.window_stats().unwrap();
+//! assert_eq!(stats.median_ratio, 0.5);
+//! assert_eq!(stats.median_size, 1000);
+//! assert_eq!(mvc.get_compression_value(), -190); // The outlier still counted as compressible
+//! ```
+//! 
+//! ## Regime Changes
+//! 
+//! Because the score moves a few points per block, a stream that switches from text
+//! to encrypted data can take dozens of blocks to cross zero. With
+//! `MVCompressionConfig::regime_detection` set, a CUSUM change-point detector watches
+//! block ratios against the ratio predicted by the moving averages; when the
+//! distribution shifts it jump-starts the score and re-seeds the averages, and
+//! reports a `RegimeChange` to an optional callback.
+//! 
+//! ```rust
+//! use mvcompression::{MVCompression, MVCompressionConfig, RegimeDetectionConfig};
+//! 
+//! let mvc = MVCompression::with_config(MVCompressionConfig {
+//!     regime_detection: Some(RegimeDetectionConfig::default()),
+//!     ..MVCompressionConfig::default()
+//! });
+//! 
+//! for _ in 0..50 {
+//!     mvc.update_compression_ratio(300, 1000); // Text
+//! }
+//! mvc.update_compression_ratio(1000, 1000); // Encrypted
+//! assert_eq!(mvc.regime_changes(), 1);
+//! assert!(mvc.get_compression_value() > 0);
+//! ```
+//! 
+//! ## Deterministic Testing
+//! 
+//! Time and randomness only reach `MVCompression` through the `Clock` and `Rng`
+//! traits. Production instances use `SystemClock` and `SystemRng`; tests build
+//! instances with `ManualClock` and `SeededRng` and can assert exact decision
+//! sequences, including randomized compression probes (`MVCompressionConfig::probe_rate`).
+//! 
+//! ```rust
+//! use mvcompression::{MVCompression, MVCompressionConfig, ManualClock, SeededRng};
+//! 
+//! let config = MVCompressionConfig { probe_rate: 4, ..MVCompressionConfig::default() };
+//! let run = || {
+//!     let mvc = MVCompression::builder()
+//!         .config(config)
+//!         .clock(ManualClock::new())
+//!         .rng(SeededRng::new(7))
+//!         .build();
+//!     (0..200)
+//!         .map(|_| {
+//!             let skip = mvc.should_skip_compression(1000);
+//!             if !skip {
+//!                 mvc.update_compression_ratio(1000, 1000);
+//!             }
+//!             skip
+//!         })
+//!         .collect::<Vec<_>>()
+//! };
+//! assert_eq!(run(), run());
+//! ```
+//! 
+//! ## `no_std` Support
+//! 
+//! The decision state is a fixed set of atomics and never allocates. Disabling
+//! the default `std` feature builds the crate with `#![no_std]`:
+//! 
+//! ```toml
+//! [dependencies]
+//! mvcompression = { version = "0.1", default-features = false }
+//! ```
+//! 
+//! Without `std` there is no system time source, so [`DefaultClock`] is a
+//! [`ManualClock`] (inject your own [`Clock`] through the builder to use score
+//! decay), and [`SystemRng`] starts from a fixed seed. On targets without native
+//! 64-bit atomics the state is backed by `portable-atomic`; targets without
+//! compare-and-swap at all additionally need the `critical-section` feature and
+//! a `critical-section` implementation.
+//! 
 //! ## Performance Characteristics
 //! 
 //! - **Lock-free**: All operations use atomic compare-and-swap loops
-//! - **Memory efficient**: Only three atomic values per instance
+//! - **Memory efficient**: Fixed-size state, no heap allocation
 //! - **Low overhead**: Minimal computation per decision
 //! - **Scalable**: Performance doesn't degrade with thread count
+//! - **Layout control**: [`CachePadded`] keeps hot atomics on separate cache lines
+//!   for heavily contended instances (see [`layout`])
+//! - **Sharding**: with `std`, `ShardedMVCompression` gives every thread its own
+//!   state and periodically consolidates the shards into one global view
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
 
+mod atomic;
+mod average;
+pub mod builder;
+pub mod clock;
+pub mod config;
+pub mod decision;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod histogram;
+#[cfg(feature = "std")]
+pub mod exposition;
+pub mod layout;
+pub mod mode;
 pub mod mvcompression;
+pub mod observer;
+pub mod publish;
+mod ratio;
+pub mod recorder;
+pub mod regime;
+pub mod replay;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod sharded;
+pub mod snapshot;
+pub mod stats;
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod tracelog;
+#[cfg(feature = "std")]
+pub mod tune;
+pub mod window;
 
+pub use builder::MVCompressionBuilder;
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+pub use clock::{Clock, DefaultClock, ManualClock};
+pub use average::AVERAGE_FRACTION_BITS;
+pub use config::{DecayConfig, DecayTarget, Hysteresis, MVCompressionConfig, SkipWindow};
+pub use decision::{Decision, DecisionReason};
+pub use error::UpdateError;
+#[cfg(feature = "std")]
+pub use histogram::{Histogram, HistogramBucket, Histograms};
+#[cfg(feature = "std")]
+pub use exposition::PrometheusExposition;
+pub use layout::{CachePadded, Compact, Layout};
+pub use mode::{PolicyMode, SkipState};
 pub use mvcompression::MVCompression;
+pub use observer::{
+    Event, EventKind, EventReason, FnObserver, NoopObserver, Observer, TraceEntry,
+};
+pub use publish::MetricsConfig;
+pub use ratio::Ratio;
+pub use recorder::LocalRecorder;
+pub use regime::{RegimeChange, RegimeDetectionConfig, RegimeShift};
+pub use replay::{ReplayBlock, ReplayReport, Simulator};
+pub use rng::{Rng, SeededRng, SystemRng};
+#[cfg(feature = "std")]
+pub use sharded::{ShardedMVCompression, ShardingConfig};
+pub use snapshot::Snapshot;
+pub use stats::PolicyStats;
+pub use trace::{TraceConfig, TraceLevel};
+#[cfg(feature = "std")]
+pub use tracelog::TraceRecorder;
+#[cfg(feature = "std")]
+pub use tune::{Tuner, Tuning};
+pub use window::{Statistics, WindowStatistic, WindowStats, WINDOW_CAPACITY};
@@ -0,0 +1,171 @@
+//! Decision modes: operator overrides and the adaptive skip state.
+//!
+//! Operators sometimes need to pin the behavior of a running process, e.g.
+//! to stop compressing while a CPU-bound incident is investigated, without
+//! redeploying. The mode is stored in one atomic byte next to the learned
+//! state, so switching it takes effect for the next decision on every thread.
+//!
+//! In [`PolicyMode::Adaptive`] an instance is either compressing or skipping
+//! ([`SkipState`]); the state switches when the score crosses the configured
+//! [`Hysteresis`](crate::Hysteresis) thresholds.
+
+use crate::atomic::{AtomicU8, Ordering};
+
+/// How [`MVCompression::should_skip_compression`](crate::MVCompression::should_skip_compression)
+/// decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyMode {
+    /// Decide from the learned state (the default).
+    #[default]
+    Adaptive,
+    /// Never skip compression.
+    AlwaysCompress {
+        /// Keep learning from reported outcomes while the override is active,
+        /// so adaptive decisions resume from an up-to-date state.
+        record_outcomes: bool,
+    },
+    /// Always skip compression.
+    NeverCompress {
+        /// Keep learning from reported outcomes while the override is active.
+        record_outcomes: bool,
+    },
+}
+
+impl PolicyMode {
+    /// Returns true if outcomes reported in this mode update the learned state.
+    pub const fn records_outcomes(self) -> bool {
+        match self {
+            PolicyMode::Adaptive => true,
+            PolicyMode::AlwaysCompress { record_outcomes }
+            | PolicyMode::NeverCompress { record_outcomes } => record_outcomes,
+        }
+    }
+
+    const fn encode(self) -> u8 {
+        match self {
+            PolicyMode::Adaptive => ADAPTIVE,
+            PolicyMode::AlwaysCompress { record_outcomes } => {
+                ALWAYS_COMPRESS | record_flag(record_outcomes)
+            }
+            PolicyMode::NeverCompress { record_outcomes } => {
+                NEVER_COMPRESS | record_flag(record_outcomes)
+            }
+        }
+    }
+
+    const fn decode(bits: u8) -> Self {
+        let record_outcomes = bits & RECORD_OUTCOMES != 0;
+        match bits & !RECORD_OUTCOMES {
+            ALWAYS_COMPRESS => PolicyMode::AlwaysCompress { record_outcomes },
+            NEVER_COMPRESS => PolicyMode::NeverCompress { record_outcomes },
+            _ => PolicyMode::Adaptive,
+        }
+    }
+}
+
+const ADAPTIVE: u8 = 0;
+const ALWAYS_COMPRESS: u8 = 1;
+const NEVER_COMPRESS: u8 = 2;
+const RECORD_OUTCOMES: u8 = 0x80;
+
+const fn record_flag(record_outcomes: bool) -> u8 {
+    if record_outcomes {
+        RECORD_OUTCOMES
+    } else {
+        0
+    }
+}
+
+/// A [`PolicyMode`] that can be switched concurrently.
+#[derive(Debug)]
+pub(crate) struct AtomicMode(AtomicU8);
+
+impl AtomicMode {
+    pub(crate) const fn new(mode: PolicyMode) -> Self {
+        Self(AtomicU8::new(mode.encode()))
+    }
+
+    pub(crate) fn load(&self) -> PolicyMode {
+        PolicyMode::decode(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Stores `mode` and returns the previous mode.
+    pub(crate) fn swap(&self, mode: PolicyMode) -> PolicyMode {
+        PolicyMode::decode(self.0.swap(mode.encode(), Ordering::Relaxed))
+    }
+}
+
+/// Whether an adaptive instance is currently skipping compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SkipState {
+    /// Every block is compressed (the initial state).
+    #[default]
+    Compressing,
+    /// Blocks inside the skip window are skipped, apart from probes.
+    Skipping,
+}
+
+/// A [`SkipState`] that can be switched concurrently.
+#[derive(Debug)]
+pub(crate) struct AtomicSkipState(AtomicU8);
+
+impl AtomicSkipState {
+    pub(crate) const fn new() -> Self {
+        Self(AtomicU8::new(0))
+    }
+
+    pub(crate) fn load(&self) -> SkipState {
+        match self.0.load(Ordering::Relaxed) {
+            0 => SkipState::Compressing,
+            _ => SkipState::Skipping,
+        }
+    }
+
+    /// Switches from `current` to `new`; returns false if another thread
+    /// switched first.
+    pub(crate) fn transition(&self, current: SkipState, new: SkipState) -> bool {
+        self.0
+            .compare_exchange(
+                current as u8,
+                new as u8,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    pub(crate) fn store(&self, state: SkipState) {
+        self.0.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes_round_trip() {
+        let modes = [
+            PolicyMode::Adaptive,
+            PolicyMode::AlwaysCompress {
+                record_outcomes: true,
+            },
+            PolicyMode::AlwaysCompress {
+                record_outcomes: false,
+            },
+            PolicyMode::NeverCompress {
+                record_outcomes: true,
+            },
+            PolicyMode::NeverCompress {
+                record_outcomes: false,
+            },
+        ];
+        let mode = AtomicMode::new(PolicyMode::Adaptive);
+        let mut previous = PolicyMode::Adaptive;
+        for next in modes {
+            assert_eq!(mode.swap(next), previous);
+            assert_eq!(mode.load(), next);
+            previous = next;
+        }
+    }
+}
@@ -1,48 +1,35 @@
-/// Thread-safe adaptive compression decision system module.
-/// 
-/// This module implements the core MVCompression algorithm that learns from past
-/// compression performance to make intelligent decisions about when to skip
-/// compression attempts.
-
-use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
-
-/// Compression ratio threshold above which a block is considered poorly compressible.
-/// Blocks with ratio > 0.9 (i.e., compressed size is more than 90% of original) 
-/// are treated as non-compressible.
-const BLOCK_COMPRESSABLE_RATIO: f32 = 0.9;
-
-/// Weight adjustment for blocks that compress well (ratio ≤ 0.9).
-/// Negative value decreases compression_value, making skipping less likely.
-const COMPRESSIBLE_BLOCK_WEIGHT: i32 = -10;
-
-/// Weight adjustment for blocks that compress poorly (ratio > 0.9).
-/// Positive value increases compression_value, making skipping more likely.
-const NON_COMPRESSIBLE_BLOCK_WEIGHT: i32 = 4;
-
-/// Weight adjustment when compression is skipped.
-/// Negative value provides feedback to eventually retry compression.
-const SKIP_COMPRESSION_BLOCK_WEIGHT: i32 = -1;
-
-/// Initial compression value when algorithm starts.
-/// Negative value ensures compression is attempted initially.
-const INITIAL_COMPRESSION_VALUE: i32 = -80;
-
-/// Maximum allowed compression value.
-/// Prevents the algorithm from becoming permanently skip-heavy.
-const MAX_COMPRESSION_VALUE: i32 = 200;
+//! Thread-safe adaptive compression decision system module.
+//!
+//! This module implements the core MVCompression algorithm that learns from past
+//! compression performance to make intelligent decisions about when to skip
+//! compression attempts.
 
-/// Minimum allowed compression value.
-/// Prevents the algorithm from becoming permanently compression-heavy.
-const MIN_COMPRESSION_VALUE: i32 = -300;
+use crate::atomic::{AtomicI32, AtomicU64, Ordering};
+use crate::average::MovingAverage;
+use crate::builder::MVCompressionBuilder;
+use crate::clock::{Clock, DefaultClock};
+use crate::config::{DecayConfig, DecayTarget, MVCompressionConfig};
+use crate::decision::{Decision, DecisionReason};
+use crate::error::UpdateError;
+#[cfg(feature = "std")]
+use crate::histogram::{AtomicHistograms, Histograms};
+use crate::layout::{Compact, Layout, Slot};
+use crate::mode::{AtomicMode, AtomicSkipState, PolicyMode, SkipState};
+use crate::observer::{Event, EventKind, EventReason, NoopObserver, Observer, TraceEntry};
+#[cfg(feature = "metrics")]
+use crate::publish::Publisher;
+use crate::ratio::{self, fixed_ratio};
+use crate::recorder::{LocalRecorder, OutcomeBatch};
+use crate::regime::{RegimeChange, RegimeDetectionConfig, RegimeDetector, RegimeShift};
+use crate::snapshot::{self, Snapshot};
+use crate::stats::{saved_bytes, Counters, PolicyStats};
+#[cfg(feature = "tracing")]
+use crate::trace::Tracer;
+use crate::window::{SlidingWindow, Statistics, WindowStats};
+use crate::rng::{Rng, SystemRng};
 
-/// Bit shift factor for smoothing in moving average calculation.
-/// Used to divide values: (value >> SMOOTHING_FACTOR) = value / 8
-const SMOOTHING_FACTOR: usize = 3;
-
-/// Weight for previous values in moving average calculation.
-/// Formula: new_avg = (old_avg >> 3) * 7 + (new_value >> 3)
-/// This gives ~87.5% weight to historical data, 12.5% to new data.
-const PREVIOUS_WEIGHT: usize = 7;
+/// Sentinel stored in `last_decay_nanos` before the clock has been read once.
+const DECAY_UNINITIALIZED: u64 = u64::MAX;
 
 /// A thread-safe adaptive compression decision system that learns from past
 /// compression performance to decide whether to compress future data blocks.
@@ -58,19 +45,38 @@ const PREVIOUS_WEIGHT: usize = 7;
 /// - Increases by 4 for poor compression (ratio > 0.9)
 /// - Decreases by 1 when compression is skipped
 /// - Bounded between -300 and +200
+/// - Optionally relaxes toward a target over wall-clock time (see [`DecayConfig`])
 /// 
 /// ## Skip Logic
 /// When compression_value > 0:
 /// - Compare incoming block size to uncompressed moving average
-/// - Skip if block_size ≤ average + (average / 4)  [within 125% of expected]
+/// - Skip if block_size ≤ average + (average / 4)  [within 125% of expected];
+///   the band is configurable (see [`SkipWindow`](crate::SkipWindow))
 /// - Update compression_value and return true
+/// - With probing enabled, randomly attempt compression for a fraction of
+///   would-be skips (see [`MVCompressionConfig::probe_rate`])
 /// 
 /// ## Moving Averages
 /// Uses exponential moving average with 87.5% weight on historical data:
-/// - `new_avg = (old_avg >> 3) * 7 + (new_value >> 3)`
-/// - Tracks both compressed and uncompressed block sizes
+/// - `new_avg = old_avg + (new_value - old_avg) / 8`, seeded by the first sample
+/// - Kept in fixed point with 16 fractional bits, so small blocks are tracked exactly
+/// - Tracks compressed and uncompressed block sizes and the compression ratio
 /// - Used for predicting compression effectiveness
 /// 
+/// ## Window Statistics
+/// With [`MVCompressionConfig::statistics`] set to [`Statistics::Window`], blocks
+/// are classified by a robust statistic (mean, median or p90) of the ratios of
+/// the last few outcomes, and the skip logic uses the same statistic of their
+/// sizes in place of the uncompressed moving average.
+/// 
+/// ## Regime Changes
+/// With [`MVCompressionConfig::regime_detection`] set, a CUSUM detector compares
+/// every block's ratio to the ratio predicted by the averages. A sustained shift
+/// jump-starts the compression value and re-seeds the averages instead of waiting
+/// for the score to walk across its range.
+/// 
+/// All of the numbers above are defaults; see [`MVCompressionConfig`].
+/// 
 /// # Thread Safety
 /// 
 /// All operations use lock-free atomic compare-and-swap loops, making the structure
@@ -110,13 +116,50 @@ const PREVIOUS_WEIGHT: usize = 7;
 /// println!("Average uncompressed size: {}", mvc.get_uncompressed_average());
 /// ```
 #[derive(Debug)]
-pub struct MVCompression {
+pub struct MVCompression<C = DefaultClock, R = SystemRng, L: Layout = Compact, O = NoopObserver> {
     /// Current compression decision value. Positive values enable skip logic.
-    compression_value: AtomicI32,
-    /// Moving average of compressed block sizes (smoothed with bit shifts).
-    compressed_size_moving_average: AtomicUsize,
-    /// Moving average of uncompressed block sizes (smoothed with bit shifts).
-    uncompressed_size_moving_average: AtomicUsize,
+    compression_value: Slot<AtomicI32, L>,
+    /// Moving average of compressed block sizes (fixed point).
+    compressed_size_moving_average: Slot<MovingAverage, L>,
+    /// Moving average of uncompressed block sizes (fixed point).
+    uncompressed_size_moving_average: Slot<MovingAverage, L>,
+    /// Moving average of per-block compression ratios (fixed point).
+    ratio_moving_average: Slot<MovingAverage, L>,
+    /// Clock reading at which decay was last applied.
+    last_decay_nanos: Slot<AtomicU64, L>,
+    /// Change-point detector state for regime detection.
+    regime_detector: Slot<RegimeDetector, L>,
+    /// Most recent outcomes, used when window statistics are configured.
+    window: Slot<SlidingWindow, L>,
+    /// Histograms of reported blocks, allocated on first use when configured.
+    #[cfg(feature = "std")]
+    histograms: std::sync::OnceLock<std::boxed::Box<AtomicHistograms>>,
+    /// Decision and outcome counts, written on every call.
+    counters: Slot<Counters, L>,
+    /// Number of regime changes detected so far. Rarely written, so not padded.
+    regime_changes: AtomicU64,
+    /// Operator override of the adaptive decision. Rarely written, so not padded.
+    mode: AtomicMode,
+    /// Whether adaptive decisions currently skip. Rarely written, so not padded.
+    skip_state: AtomicSkipState,
+    /// Number of transitions into the skipping state.
+    skip_entered: AtomicU64,
+    /// Number of transitions out of the skipping state.
+    skip_exited: AtomicU64,
+    /// Algorithm parameters, fixed at construction.
+    config: MVCompressionConfig,
+    /// Time source for decay.
+    clock: C,
+    /// Random source for probing.
+    rng: R,
+    /// Receiver of notable events.
+    observer: O,
+    /// Sampling state of `tracing` instrumentation.
+    #[cfg(feature = "tracing")]
+    tracer: Tracer,
+    /// Handles of metrics published through the `metrics` facade.
+    #[cfg(feature = "metrics")]
+    publisher: Publisher,
 }
 
 impl Default for MVCompression {
@@ -127,14 +170,99 @@ impl Default for MVCompression {
 
 impl MVCompression {
     /// Creates a new MVCompression instance with default values.
-    pub fn new() -> Self {
+    ///
+    /// This is a `const fn`, so a process-wide policy needs no lazy initialization:
+    ///
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    ///
+    /// static POLICY: MVCompression = MVCompression::new();
+    ///
+    /// if !POLICY.should_skip_compression(1024) {
+    ///     POLICY.update_compression_ratio(512, 1024);
+    /// }
+    /// assert_eq!(POLICY.get_compression_value(), -90);
+    /// ```
+    pub const fn new() -> Self {
+        Self::with_config(MVCompressionConfig::new())
+    }
+
+    /// Creates a new MVCompression instance with the given configuration.
+    ///
+    /// Like [`new`](Self::new) this is a `const fn`; configurations can be built
+    /// in constant context with struct update syntax on top of
+    /// [`MVCompressionConfig::new`]:
+    ///
+    /// ```rust
+    /// use mvcompression::{MVCompression, MVCompressionConfig};
+    ///
+    /// static POLICY: MVCompression = MVCompression::with_config(MVCompressionConfig {
+    ///     probe_rate: 16,
+    ///     ..MVCompressionConfig::new()
+    /// });
+    ///
+    /// assert_eq!(POLICY.config().probe_rate, 16);
+    /// ```
+    pub const fn with_config(config: MVCompressionConfig) -> Self {
+        Self::from_parts(config, DefaultClock::new(), SystemRng, NoopObserver)
+    }
+
+    /// Returns a builder for instances with injected dependencies such as a clock
+    /// or random source.
+    pub const fn builder() -> MVCompressionBuilder {
+        MVCompressionBuilder::new()
+    }
+}
+
+impl<C: Clock, R: Rng, L: Layout, O: Observer> MVCompression<C, R, L, O> {
+    pub(crate) const fn from_parts(config: MVCompressionConfig, clock: C, rng: R, observer: O) -> Self {
         Self {
-            compression_value: AtomicI32::new(INITIAL_COMPRESSION_VALUE),
-            compressed_size_moving_average: AtomicUsize::new(0),
-            uncompressed_size_moving_average: AtomicUsize::new(0),
+            compression_value: Slot::new(AtomicI32::new(config.initial_compression_value)),
+            compressed_size_moving_average: Slot::new(MovingAverage::new()),
+            uncompressed_size_moving_average: Slot::new(MovingAverage::new()),
+            ratio_moving_average: Slot::new(MovingAverage::new()),
+            last_decay_nanos: Slot::new(AtomicU64::new(DECAY_UNINITIALIZED)),
+            regime_detector: Slot::new(RegimeDetector::new()),
+            window: Slot::new(SlidingWindow::new()),
+            #[cfg(feature = "std")]
+            histograms: std::sync::OnceLock::new(),
+            counters: Slot::new(Counters::new()),
+            regime_changes: AtomicU64::new(0),
+            mode: AtomicMode::new(PolicyMode::Adaptive),
+            skip_state: AtomicSkipState::new(),
+            skip_entered: AtomicU64::new(0),
+            skip_exited: AtomicU64::new(0),
+            config,
+            clock,
+            rng,
+            observer,
+            #[cfg(feature = "tracing")]
+            tracer: Tracer::new(),
+            #[cfg(feature = "metrics")]
+            publisher: Publisher::new(),
         }
     }
 
+    /// Returns the configuration this instance was created with.
+    pub fn config(&self) -> &MVCompressionConfig {
+        &self.config
+    }
+
+    /// Returns the clock used for time-based behavior.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Returns the random source used for randomized behavior.
+    pub fn rng(&self) -> &R {
+        &self.rng
+    }
+
+    /// Returns the observer receiving this instance's events.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
     /// Determines whether compression should be skipped for a block of the given size.
     /// 
     /// This is the main decision function of the algorithm. It uses the current
@@ -144,7 +272,8 @@ impl MVCompression {
     /// # Algorithm
     /// 1. If compression_value ≤ 0: always return false (always compress)
     /// 2. If compression_value > 0: check if block size is within expected range
-    /// 3. If within range (≤ 125% of average): skip compression and update value
+    /// 3. If within range (≤ 125% of average): skip compression and update value,
+    ///    unless the block is randomly chosen as a probe
     /// 4. If outside range: don't skip (attempt compression)
     /// 
     /// # Thread Safety
@@ -174,63 +303,255 @@ impl MVCompression {
     /// // Now may skip similar-sized blocks
     /// ```
     pub fn should_skip_compression(&self, datasize: usize) -> bool {
+        self.decide(datasize).skip
+    }
+
+    /// Decides like [`should_skip_compression`](Self::should_skip_compression)
+    /// and also reports why.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{DecisionReason, MVCompression};
+    ///
+    /// let mvc = MVCompression::new();
+    /// let decision = mvc.decide(1000);
+    /// assert!(!decision.skip);
+    /// assert_eq!(decision.reason, DecisionReason::Compressing);
+    /// ```
+    pub fn decide(&self, datasize: usize) -> Decision {
+        #[cfg(feature = "tracing")]
+        let traced = self
+            .tracer
+            .decision_level(&self.config.trace)
+            .map(|level| (level, self.compression_value.load(Ordering::Relaxed)));
+        let decision = self.evaluate(datasize);
+        if self.config.counters {
+            self.counters.record_decision(decision.skip);
+        }
+        if self.observer.wants_trace() {
+            self.observer.on_trace(&TraceEntry::Decision {
+                timestamp_nanos: self.clock.now_nanos(),
+                size: datasize,
+                decision,
+                score: self.compression_value.load(Ordering::Relaxed),
+            });
+        }
+        #[cfg(feature = "metrics")]
+        self.publisher.decision(
+            &self.config.metrics,
+            decision,
+            self.compression_value.load(Ordering::Relaxed),
+        );
+        #[cfg(feature = "tracing")]
+        if let Some((level, score_before)) = traced {
+            let score_after = self.compression_value.load(Ordering::Relaxed);
+            Tracer::decision(level, datasize, decision, score_before, score_after);
+        }
+        decision
+    }
+
+    /// Makes the decision reported by [`decide`](Self::decide).
+    fn evaluate(&self, datasize: usize) -> Decision {
+        match self.mode.load() {
+            PolicyMode::Adaptive => {}
+            PolicyMode::AlwaysCompress { .. } => return Decision::compress(DecisionReason::Overridden),
+            PolicyMode::NeverCompress { .. } => return Decision::skip(DecisionReason::Overridden),
+        }
+        self.apply_decay();
         let current_compression_value = self.compression_value.load(Ordering::Relaxed);
-        if current_compression_value > 0 {
-            let expected_size = self.uncompressed_size_moving_average.load(Ordering::Relaxed);
-            if datasize <= expected_size + (expected_size >> 2) {
-                // Use compare_and_swap loop to safely update compression_value
-                loop {
-                    let current = self.compression_value.load(Ordering::Relaxed);
-                    let new_value = current + SKIP_COMPRESSION_BLOCK_WEIGHT;
-                    match self.compression_value.compare_exchange_weak(
-                        current,
-                        new_value,
-                        Ordering::Relaxed,
-                        Ordering::Relaxed,
-                    ) {
-                        Ok(_) => break,
-                        Err(_) => continue, // Retry if another thread modified the value
-                    }
-                }
-                return true;
+        if self.update_skip_state(current_compression_value, datasize) == SkipState::Compressing {
+            return Decision::compress(DecisionReason::Compressing);
+        }
+        let expected_size = self.expected_size();
+        if !self.config.skip_window.contains(expected_size, datasize) {
+            return Decision::compress(DecisionReason::OutsideWindow);
+        }
+        if self.is_probe() {
+            return Decision::compress(DecisionReason::Probe);
+        }
+        // Use compare_and_swap loop to safely update compression_value
+        let (previous, new_value) = loop {
+            let current = self.compression_value.load(Ordering::Relaxed);
+            let new_value = current.saturating_add(self.config.skip_compression_block_weight);
+            match self.compression_value.compare_exchange_weak(
+                current,
+                new_value,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break (current, new_value),
+                Err(_) => continue, // Retry if another thread modified the value
+            }
+        };
+        self.notify_bounds(previous, new_value, EventReason::Decision, None, Some(datasize));
+        Decision::skip(DecisionReason::Skipped)
+    }
+
+    /// Moves the skip state across the hysteresis thresholds for `value` and
+    /// returns the resulting state.
+    ///
+    /// Only the thread whose compare-exchange performs a transition counts and
+    /// reports it.
+    fn update_skip_state(&self, value: i32, datasize: usize) -> SkipState {
+        let hysteresis = self.config.hysteresis;
+        let current = self.skip_state.load();
+        let (next, counter, kind) = match current {
+            SkipState::Compressing if value > hysteresis.enter_above => {
+                (SkipState::Skipping, &self.skip_entered, EventKind::SkipStarted)
+            }
+            SkipState::Skipping if value < hysteresis.exit_below => {
+                (SkipState::Compressing, &self.skip_exited, EventKind::SkipStopped)
             }
+            _ => return current,
+        };
+        if self.skip_state.transition(current, next) {
+            counter.fetch_add(1, Ordering::Relaxed);
+            if O::ENABLED {
+                self.observer.on_event(&Event {
+                    kind,
+                    reason: EventReason::Decision,
+                    previous_value: value,
+                    new_value: value,
+                    compressed: None,
+                    uncompressed: Some(datasize),
+                });
+            }
+            return next;
+        }
+        self.skip_state.load()
+    }
+
+    /// Reports the score reaching a bound, if the change from `previous` to
+    /// `new_value` crossed one.
+    fn notify_bounds(
+        &self,
+        previous: i32,
+        new_value: i32,
+        reason: EventReason,
+        compressed: Option<usize>,
+        uncompressed: Option<usize>,
+    ) {
+        if !O::ENABLED {
+            return;
+        }
+        let max = self.config.max_compression_value;
+        let min = self.config.min_compression_value;
+        let kind = if previous < max && new_value >= max {
+            EventKind::ReachedMax
+        } else if previous > min && new_value <= min {
+            EventKind::ReachedMin
+        } else {
+            return;
+        };
+        self.observer.on_event(&Event {
+            kind,
+            reason,
+            previous_value: previous,
+            new_value,
+            compressed,
+            uncompressed,
+        });
+    }
+
+    /// Reports a block whose size is far from the uncompressed average.
+    pub(crate) fn notify_size_outlier(&self, compressed: usize, uncompressed: usize) {
+        let factor = self.config.size_outlier_factor as usize;
+        if !O::ENABLED || factor == 0 {
+            return;
         }
-        false
+        if self.uncompressed_size_moving_average.get().is_none() {
+            return;
+        }
+        let expected = self.uncompressed_size_moving_average.get_rounded().max(1);
+        if uncompressed <= expected.saturating_mul(factor) && uncompressed.saturating_mul(factor) >= expected {
+            return;
+        }
+        let value = self.compression_value.load(Ordering::Relaxed);
+        self.observer.on_event(&Event {
+            kind: EventKind::SizeOutlier,
+            reason: EventReason::Outcome,
+            previous_value: value,
+            new_value: value,
+            compressed: Some(compressed),
+            uncompressed: Some(uncompressed),
+        });
+    }
+
+    /// Returns the block size the skip window is centered on.
+    fn expected_size(&self) -> usize {
+        match self.config.statistics {
+            Statistics::MovingAverage => self.uncompressed_size_moving_average.get_rounded(),
+            Statistics::Window { size, statistic } => self
+                .window
+                .stats(size)
+                .map_or(0, |stats| stats.size(statistic)),
+        }
+    }
+
+    /// Returns true if a would-be skip should attempt compression instead.
+    ///
+    /// Draws from the random source only when probing is enabled.
+    fn is_probe(&self) -> bool {
+        let probe_rate = self.config.probe_rate;
+        probe_rate > 0 && self.rng.next_u64().is_multiple_of(u64::from(probe_rate))
     }
 
-    /// Updates the moving averages for compressed and uncompressed block sizes.
+    /// Relaxes the compression value toward its decay target for the time
+    /// elapsed since decay was last applied.
     /// 
-    /// This method uses lock-free atomic operations to safely update the moving
-    /// averages from multiple threads.
+    /// Does nothing unless [`MVCompressionConfig::decay`] is set, so instances
+    /// without decay never read their clock.
     /// 
-    /// # Arguments
-    /// * `compressed` - The size of the compressed block
-    /// * `uncompressed` - The size of the uncompressed block
-    fn update_compression_block_size(&self, compressed: usize, uncompressed: usize) {
-        // Update compressed size moving average atomically
-        loop {
-            let current_compressed = self.compressed_size_moving_average.load(Ordering::Relaxed);
-            let new_compressed = (current_compressed >> SMOOTHING_FACTOR) * PREVIOUS_WEIGHT
-                + (compressed >> SMOOTHING_FACTOR);
-            match self.compressed_size_moving_average.compare_exchange_weak(
-                current_compressed,
-                new_compressed,
+    /// # Thread Safety
+    /// The time the value needs to move is claimed with a compare-exchange on
+    /// the decay timestamp before the value is touched, so concurrent callers
+    /// never apply decay for the same interval twice. Time that does not move
+    /// the value yet stays unclaimed.
+    fn apply_decay(&self) {
+        let Some(decay) = self.config.decay else {
+            return;
+        };
+
+        let now = self.clock.now_nanos();
+        let last = self.last_decay_nanos.load(Ordering::Relaxed);
+        if last == DECAY_UNINITIALIZED {
+            // First observation only establishes the reference point.
+            let _ = self.last_decay_nanos.compare_exchange(
+                DECAY_UNINITIALIZED,
+                now,
                 Ordering::Relaxed,
                 Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(_) => continue,
-            }
+            );
+            return;
+        }
+        if now <= last {
+            return;
+        }
+
+        let elapsed = now - last;
+        let target = self.decay_target(&decay);
+        let current = self.compression_value.load(Ordering::Relaxed);
+        let Some((_, consumed)) = decay_step(current, target, elapsed, &decay) else {
+            // Too little time has passed to move the value; keep accumulating.
+            return;
+        };
+
+        if self
+            .last_decay_nanos
+            .compare_exchange(last, last + consumed, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return; // Another thread claimed this interval
         }
 
-        // Update uncompressed size moving average atomically
         loop {
-            let current_uncompressed = self.uncompressed_size_moving_average.load(Ordering::Relaxed);
-            let new_uncompressed = (current_uncompressed >> SMOOTHING_FACTOR) * PREVIOUS_WEIGHT
-                + (uncompressed >> SMOOTHING_FACTOR);
-            match self.uncompressed_size_moving_average.compare_exchange_weak(
-                current_uncompressed,
-                new_uncompressed,
+            let current = self.compression_value.load(Ordering::Relaxed);
+            let Some((new_value, _)) = decay_step(current, target, elapsed, &decay) else {
+                break; // The value moved meanwhile and the elapsed time no longer moves it
+            };
+            match self.compression_value.compare_exchange_weak(
+                current,
+                new_value,
                 Ordering::Relaxed,
                 Ordering::Relaxed,
             ) {
@@ -240,6 +561,33 @@ impl MVCompression {
         }
     }
 
+    fn decay_target(&self, decay: &DecayConfig) -> i32 {
+        match decay.target {
+            DecayTarget::Initial => self.config.initial_compression_value,
+            DecayTarget::Zero => 0,
+        }
+    }
+
+    /// Updates the moving averages for compressed and uncompressed block sizes
+    /// and for the compression ratio.
+    /// 
+    /// This method uses lock-free atomic operations to safely update the moving
+    /// averages from multiple threads.
+    /// 
+    /// # Arguments
+    /// * `compressed` - The size of the compressed block
+    /// * `uncompressed` - The size of the uncompressed block
+    fn update_compression_block_size(&self, compressed: usize, uncompressed: usize) {
+        let smoothing = self.config.smoothing_factor;
+        self.compressed_size_moving_average
+            .update(MovingAverage::to_fixed(compressed), smoothing);
+        self.uncompressed_size_moving_average
+            .update(MovingAverage::to_fixed(uncompressed), smoothing);
+        if let Some(ratio) = fixed_ratio(compressed, uncompressed) {
+            self.ratio_moving_average.update(ratio, smoothing);
+        }
+    }
+
     /// Updates the compression decision algorithm based on actual compression results.
     /// 
     /// This method should be called after compressing a block to inform the algorithm
@@ -247,9 +595,13 @@ impl MVCompression {
     /// and the compression value based on the compression ratio.
     /// 
     /// # Algorithm Steps
-    /// 1. Calculate compression ratio = compressed_size / uncompressed_size
-    /// 2. Update moving averages for both compressed and uncompressed sizes
-    /// 3. Adjust compression_value based on ratio:
+    /// 1. Compare compressed_size / uncompressed_size against the threshold using
+    ///    integer cross-multiplication (with window statistics: record the block
+    ///    and compare the configured statistic of the window's ratios)
+    /// 2. If regime detection is enabled and the ratio distribution has shifted,
+    ///    jump-start compression_value and re-seed the moving averages
+    /// 3. Update moving averages for both compressed and uncompressed sizes
+    /// 4. Adjust compression_value based on ratio:
     ///    - If ratio > 0.9 (poor): add +4 (bounded by MAX_COMPRESSION_VALUE)
     ///    - If ratio ≤ 0.9 (good): add -10 (bounded by MIN_COMPRESSION_VALUE)
     /// 
@@ -277,52 +629,591 @@ impl MVCompression {
     /// assert!(mvc2.get_compression_value() > -80); // Becomes less negative
     /// ```
     /// 
-    /// # Panics
-    /// This method will not panic, but division by zero is possible if
-    /// `uncompressed` is 0. Callers should ensure uncompressed > 0.
+    /// # Invalid Input
+    /// Results rejected by [`try_update`](Self::try_update), such as an empty
+    /// uncompressed block, are silently ignored and leave the state unchanged.
     pub fn update_compression_ratio(&self, compressed: usize, uncompressed: usize) {
-        let compression_ratio = compressed as f32 / uncompressed as f32;
+        let _ = self.try_update(compressed, uncompressed);
+    }
+
+    /// Updates the algorithm like [`update_compression_ratio`](Self::update_compression_ratio),
+    /// but reports results that cannot be genuine instead of ignoring them.
+    ///
+    /// A result is rejected when `uncompressed` is zero or when `compressed`
+    /// exceeds `uncompressed` scaled by
+    /// [`MVCompressionConfig::max_expansion_ratio`] plus
+    /// [`MVCompressionConfig::expansion_allowance`] (by default `2 * uncompressed + 64`).
+    /// Rejected results leave the learned state untouched.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{MVCompression, UpdateError};
+    ///
+    /// let mvc = MVCompression::new();
+    /// assert_eq!(mvc.try_update(10, 0), Err(UpdateError::EmptyBlock));
+    /// assert_eq!(
+    ///     mvc.try_update(5000, 1000),
+    ///     Err(UpdateError::CompressedTooLarge { compressed: 5000, limit: 2064 })
+    /// );
+    /// assert_eq!(mvc.get_compression_value(), -80);
+    ///
+    /// assert_eq!(mvc.try_update(500, 1000), Ok(()));
+    /// assert_eq!(mvc.get_compression_value(), -90);
+    /// ```
+    pub fn try_update(&self, compressed: usize, uncompressed: usize) -> Result<(), UpdateError> {
+        self.validate(compressed, uncompressed)?;
+        if self.config.counters {
+            self.counters.record_outcomes(1, saved_bytes(compressed, uncompressed));
+        }
+        #[cfg(feature = "std")]
+        if self.config.histograms {
+            self.histograms
+                .get_or_init(AtomicHistograms::new)
+                .record(compressed, uncompressed);
+        }
+        if !self.mode.load().records_outcomes() {
+            self.trace_outcome(compressed, uncompressed, self.compression_value.load(Ordering::Relaxed));
+            return Ok(());
+        }
+
+        self.apply_decay();
+        let threshold = self.config.compressible_ratio;
+        let poorly_compressed = match self.config.statistics {
+            Statistics::MovingAverage => threshold.is_exceeded_by(compressed, uncompressed),
+            Statistics::Window { size, statistic } => {
+                self.window.record(compressed, uncompressed);
+                self.window.ratio(size, statistic).map_or_else(
+                    || threshold.is_exceeded_by(compressed, uncompressed),
+                    |ratio| threshold.is_exceeded_by_fixed(ratio),
+                )
+            }
+        };
+        if let Some(regime) = self.config.regime_detection {
+            self.detect_regime_change(compressed, uncompressed, &regime);
+        }
+        self.notify_size_outlier(compressed, uncompressed);
         self.update_compression_block_size(compressed, uncompressed);
-        
-        if compression_ratio > BLOCK_COMPRESSABLE_RATIO {
+
+        let (previous, new_value) = if poorly_compressed {
             // Update compression_value atomically with bounds checking
             loop {
                 let current = self.compression_value.load(Ordering::Relaxed);
-                if current < MAX_COMPRESSION_VALUE {
-                    let new_value = current + NON_COMPRESSIBLE_BLOCK_WEIGHT;
+                if current < self.config.max_compression_value {
+                    let new_value = current.saturating_add(self.config.non_compressible_block_weight);
                     match self.compression_value.compare_exchange_weak(
                         current,
                         new_value,
                         Ordering::Relaxed,
                         Ordering::Relaxed,
                     ) {
-                        Ok(_) => break,
+                        Ok(_) => break (current, new_value),
                         Err(_) => continue,
                     }
                 } else {
-                    break; // Already at max value
+                    break (current, current); // Already at max value
                 }
             }
         } else {
             // Update compression_value atomically with bounds checking
             loop {
                 let current = self.compression_value.load(Ordering::Relaxed);
-                if current > MIN_COMPRESSION_VALUE {
-                    let new_value = current + COMPRESSIBLE_BLOCK_WEIGHT;
+                if current > self.config.min_compression_value {
+                    let new_value = current.saturating_add(self.config.compressible_block_weight);
                     match self.compression_value.compare_exchange_weak(
                         current,
                         new_value,
                         Ordering::Relaxed,
                         Ordering::Relaxed,
                     ) {
-                        Ok(_) => break,
+                        Ok(_) => break (current, new_value),
                         Err(_) => continue,
                     }
                 } else {
-                    break; // Already at min value
+                    break (current, current); // Already at min value
+                }
+            }
+        };
+        self.notify_bounds(previous, new_value, EventReason::Outcome, Some(compressed), Some(uncompressed));
+        self.trace_outcome(compressed, uncompressed, new_value);
+        #[cfg(feature = "tracing")]
+        if let Some(level) = self.tracer.outcome_level(&self.config.trace) {
+            Tracer::outcome(level, compressed, uncompressed, poorly_compressed, previous, new_value);
+        }
+        #[cfg(feature = "metrics")]
+        self.publisher.outcomes(
+            &self.config.metrics,
+            compressed as f64 / uncompressed as f64,
+            1,
+            new_value,
+        );
+        Ok(())
+    }
+
+    /// Passes an accepted result to a tracing observer.
+    fn trace_outcome(&self, compressed: usize, uncompressed: usize, score: i32) {
+        if self.observer.wants_trace() {
+            self.observer.on_trace(&TraceEntry::Outcome {
+                timestamp_nanos: self.clock.now_nanos(),
+                compressed,
+                uncompressed,
+                score,
+            });
+        }
+    }
+
+    /// Passes a merged batch to a tracing observer.
+    fn trace_batch(&self, batch: &OutcomeBatch, score: i32) {
+        if self.observer.wants_trace() {
+            self.observer.on_trace(&TraceEntry::Batch {
+                timestamp_nanos: self.clock.now_nanos(),
+                blocks: batch.blocks(),
+                poorly_compressed: batch.poorly_compressed(),
+                score,
+            });
+        }
+    }
+
+    /// Rejects results that cannot come from a real compressor.
+    pub(crate) fn validate(&self, compressed: usize, uncompressed: usize) -> Result<(), UpdateError> {
+        if uncompressed == 0 {
+            return Err(UpdateError::EmptyBlock);
+        }
+        let limit = self
+            .config
+            .max_expansion_ratio
+            .scale(uncompressed)
+            .saturating_add(self.config.expansion_allowance);
+        if compressed > limit {
+            return Err(UpdateError::CompressedTooLarge { compressed, limit });
+        }
+        Ok(())
+    }
+
+    /// Returns true if validated outcomes can be aggregated before being applied.
+    ///
+    /// Window statistics, regime detection and histograms look at every block,
+    /// so with any of them enabled outcomes must be applied one at a time.
+    pub(crate) fn supports_batching(&self) -> bool {
+        self.config.statistics == Statistics::MovingAverage
+            && self.config.regime_detection.is_none()
+            && !self.config.histograms
+    }
+
+    /// Applies a batch of validated outcomes with one update per shared atomic.
+    ///
+    /// The averages move as if every block had the batch's mean size and ratio,
+    /// and the score moves by the sum of the blocks' weights, clamped to the
+    /// configured bounds. The mode was checked as each block was recorded, so
+    /// the batch applies even if the mode has changed since.
+    pub(crate) fn merge_outcomes(&self, batch: &OutcomeBatch) {
+        if batch.is_empty() {
+            return;
+        }
+        if self.config.counters {
+            self.counters.record_outcomes(batch.blocks(), batch.bytes_saved());
+        }
+        self.apply_decay();
+        let smoothing = self.config.smoothing_factor;
+        let blocks = batch.blocks();
+        self.compressed_size_moving_average
+            .update_batch(batch.mean_compressed(), blocks, smoothing);
+        self.uncompressed_size_moving_average
+            .update_batch(batch.mean_uncompressed(), blocks, smoothing);
+        self.ratio_moving_average
+            .update_batch(batch.mean_ratio(), blocks, smoothing);
+
+        let poor = i128::from(batch.poorly_compressed());
+        let good = i128::from(blocks - batch.poorly_compressed());
+        let delta = poor * i128::from(self.config.non_compressible_block_weight)
+            + good * i128::from(self.config.compressible_block_weight);
+        loop {
+            let current = self.compression_value.load(Ordering::Relaxed);
+            // Never push a value that already lies outside the bounds further out.
+            let low = self.config.min_compression_value.min(current);
+            let high = self.config.max_compression_value.max(current);
+            let new_value = (i128::from(current) + delta).clamp(i128::from(low), i128::from(high)) as i32;
+            match self.compression_value.compare_exchange_weak(
+                current,
+                new_value,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    self.notify_bounds(current, new_value, EventReason::Batch, None, None);
+                    self.trace_batch(batch, new_value);
+                    #[cfg(feature = "tracing")]
+                    if let Some(level) = self.tracer.outcome_level(&self.config.trace) {
+                        Tracer::batch(level, blocks, batch.poorly_compressed(), current, new_value);
+                    }
+                    #[cfg(feature = "metrics")]
+                    self.publisher
+                        .outcomes(&self.config.metrics, batch.mean_ratio_f64(), blocks, new_value);
+                    break;
                 }
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Returns the current skip state, how often it changed, and how many
+    /// decisions and results the instance has seen.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{MVCompression, SkipState};
+    ///
+    /// let mvc = MVCompression::new();
+    /// mvc.force_value(10);
+    /// mvc.should_skip_compression(4096);
+    /// mvc.force_value(-10);
+    /// mvc.should_skip_compression(4096);
+    ///
+    /// let stats = mvc.stats();
+    /// assert_eq!(stats.skip_state, SkipState::Compressing);
+    /// assert_eq!((stats.skip_entered, stats.skip_exited), (1, 1));
+    /// ```
+    pub fn stats(&self) -> PolicyStats {
+        let mut stats = PolicyStats {
+            skip_state: self.skip_state.load(),
+            skip_entered: self.skip_entered.load(Ordering::Relaxed),
+            skip_exited: self.skip_exited.load(Ordering::Relaxed),
+            ..PolicyStats::default()
+        };
+        self.counters.read_into(&mut stats);
+        stats
+    }
+
+    /// Returns the current operator override.
+    pub fn mode(&self) -> PolicyMode {
+        self.mode.load()
+    }
+
+    /// Switches the operator override and returns the previous mode.
+    ///
+    /// The new mode applies to the next decision on every thread. In a forced
+    /// mode decisions do not touch the score, and reported outcomes are only
+    /// learned from if the mode records them; switching back to
+    /// [`PolicyMode::Adaptive`] resumes from the state at that point.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{MVCompression, PolicyMode};
+    ///
+    /// let mvc = MVCompression::new();
+    ///
+    /// // Incident: stop compressing and ignore whatever is still reported.
+    /// mvc.set_mode(PolicyMode::NeverCompress { record_outcomes: false });
+    /// assert!(mvc.should_skip_compression(1000));
+    /// mvc.update_compression_ratio(900, 1000);
+    /// assert_eq!(mvc.get_compressed_average(), 0);
+    ///
+    /// // Compress everything, but keep learning.
+    /// mvc.set_mode(PolicyMode::AlwaysCompress { record_outcomes: true });
+    /// assert!(!mvc.should_skip_compression(1000));
+    /// mvc.update_compression_ratio(100, 1000);
+    ///
+    /// mvc.set_mode(PolicyMode::Adaptive);
+    /// assert_eq!(mvc.get_compressed_average(), 100);
+    /// ```
+    pub fn set_mode(&self, mode: PolicyMode) -> PolicyMode {
+        self.mode.swap(mode)
+    }
+
+    /// Returns a per-thread handle that batches updates into this instance.
+    ///
+    /// See [`LocalRecorder`] for the trade-offs.
+    pub fn local_recorder(&self, flush_every: usize) -> LocalRecorder<'_, C, R, L, O> {
+        LocalRecorder::new(self, flush_every)
+    }
+
+    /// Feeds a block into the regime detector and, on a detected shift, jumps the
+    /// learned state to the new regime.
+    ///
+    /// Blocks are ignored until the ratio moving average holds a prediction.
+    fn detect_regime_change(
+        &self,
+        compressed: usize,
+        uncompressed: usize,
+        regime: &RegimeDetectionConfig,
+    ) {
+        let Some(ratio) = fixed_ratio(compressed, uncompressed) else {
+            return;
+        };
+        let Some(reference) = self.ratio_moving_average.get() else {
+            return;
+        };
+        let Some(shift) = self.regime_detector.observe(ratio, reference, regime) else {
+            return;
+        };
+
+        let (previous_value, new_value) = loop {
+            let current = self.compression_value.load(Ordering::Relaxed);
+            let new_value = match shift {
+                RegimeShift::LessCompressible => current.max(0),
+                RegimeShift::MoreCompressible => current.min(self.config.initial_compression_value),
+            }
+            .clamp(self.config.min_compression_value, self.config.max_compression_value);
+            match self.compression_value.compare_exchange_weak(
+                current,
+                new_value,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break (current, new_value),
+                Err(_) => continue,
+            }
+        };
+        self.compressed_size_moving_average
+            .reset_to(MovingAverage::to_fixed(compressed));
+        self.uncompressed_size_moving_average
+            .reset_to(MovingAverage::to_fixed(uncompressed));
+        self.ratio_moving_average.reset_to(ratio);
+        self.regime_changes.fetch_add(1, Ordering::Relaxed);
+        if O::ENABLED {
+            self.observer.on_event(&Event {
+                kind: EventKind::RegimeChange,
+                reason: EventReason::RegimeChange,
+                previous_value,
+                new_value,
+                compressed: Some(compressed),
+                uncompressed: Some(uncompressed),
+            });
+        }
+        self.notify_bounds(
+            previous_value,
+            new_value,
+            EventReason::RegimeChange,
+            Some(compressed),
+            Some(uncompressed),
+        );
+
+        if let Some(on_change) = regime.on_change {
+            on_change(RegimeChange {
+                shift,
+                ratio: ratio::ratio_to_f32(ratio),
+                reference_ratio: ratio::ratio_to_f32(reference),
+                previous_value,
+                new_value,
+            });
+        }
+    }
+
+    /// Returns the learned state as a single [`Snapshot`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    ///
+    /// let mvc = MVCompression::new();
+    /// assert!(!mvc.snapshot().has_samples());
+    ///
+    /// mvc.update_compression_ratio(250, 1000);
+    /// let snapshot = mvc.snapshot();
+    /// assert_eq!(snapshot.compression_value, -90);
+    /// assert_eq!(snapshot.compressed_average, 250.0);
+    /// assert_eq!(snapshot.ratio_average, 0.25);
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            compression_value: self.get_compression_value(),
+            compressed_average: self.get_compressed_average_precise(),
+            uncompressed_average: self.get_uncompressed_average_precise(),
+            ratio_average: self.get_ratio_average(),
+            regime_changes: self.regime_changes(),
+        }
+    }
+
+    /// Overwrites the score and averages with those of `snapshot`.
+    ///
+    /// Window statistics, regime detection state and counters are left alone.
+    pub(crate) fn restore(&self, snapshot: &Snapshot) {
+        self.compression_value
+            .store(snapshot.compression_value, Ordering::Relaxed);
+        self.restore_averages(snapshot);
+    }
+
+    /// Moves the score by `merged.compression_value - seen` and overwrites the
+    /// averages with those of `merged`.
+    ///
+    /// `seen` is the score `merged` was computed from, so score changes made
+    /// since then are kept on top of the merged score.
+    #[cfg(feature = "std")]
+    pub(crate) fn rebase(&self, merged: &Snapshot, seen: i32) {
+        let delta = i64::from(merged.compression_value) - i64::from(seen);
+        loop {
+            let current = self.compression_value.load(Ordering::Relaxed);
+            // Never push a value that already lies outside the bounds further out.
+            let low = self.config.min_compression_value.min(current);
+            let high = self.config.max_compression_value.max(current);
+            let new_value = (i64::from(current) + delta).clamp(i64::from(low), i64::from(high)) as i32;
+            if self
+                .compression_value
+                .compare_exchange_weak(current, new_value, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
             }
         }
+        self.restore_averages(merged);
+    }
+
+    /// Overwrites the averages with those of `snapshot`.
+    fn restore_averages(&self, snapshot: &Snapshot) {
+        if snapshot.has_samples() {
+            self.compressed_size_moving_average
+                .reset_to(snapshot::to_fixed(snapshot.compressed_average));
+            self.uncompressed_size_moving_average
+                .reset_to(snapshot::to_fixed(snapshot.uncompressed_average));
+            self.ratio_moving_average
+                .reset_to(snapshot::to_fixed(snapshot.ratio_average));
+        } else {
+            self.compressed_size_moving_average.clear();
+            self.uncompressed_size_moving_average.clear();
+            self.ratio_moving_average.clear();
+        }
+    }
+
+    /// Forgets everything learned and starts over from the initial value.
+    ///
+    /// Clears the averages, the window, the regime detector, the histograms and
+    /// the decay reference time. The `regime_changes` counter and
+    /// [`stats`](Self::stats) are kept. Each piece of state
+    /// is reset atomically, but not all of them at once: a block recorded
+    /// concurrently by another thread may survive in some of them.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    /// use std::sync::Arc;
+    ///
+    /// let mvc = Arc::new(MVCompression::new());
+    /// mvc.update_compression_ratio(1000, 1000);
+    ///
+    /// // The schema changed; what we learned no longer applies.
+    /// mvc.reset();
+    /// assert_eq!(mvc.snapshot(), MVCompression::new().snapshot());
+    /// ```
+    pub fn reset(&self) {
+        self.start_over(&Snapshot {
+            compression_value: self.config.initial_compression_value,
+            compressed_average: 0.0,
+            uncompressed_average: 0.0,
+            ratio_average: 0.0,
+            regime_changes: 0,
+        });
+    }
+
+    /// Starts over from `snapshot` instead of the initial state.
+    ///
+    /// Like [`reset`](Self::reset), but seeds the score (clamped to the
+    /// configured bounds) and the averages from `snapshot`. Its
+    /// `regime_changes` is ignored.
+    pub fn reset_to(&self, snapshot: &Snapshot) {
+        self.start_over(&Snapshot {
+            compression_value: self.clamp_to_bounds(snapshot.compression_value),
+            ..*snapshot
+        });
+    }
+
+    fn start_over(&self, snapshot: &Snapshot) {
+        self.skip_state.store(SkipState::Compressing);
+        self.window.clear();
+        self.regime_detector.reset();
+        #[cfg(feature = "std")]
+        if let Some(histograms) = self.histograms.get() {
+            histograms.clear();
+        }
+        self.last_decay_nanos
+            .store(DECAY_UNINITIALIZED, Ordering::Relaxed);
+        self.restore(snapshot);
+    }
+
+    /// Overwrites the compression value, clamped to the configured bounds, and
+    /// returns the value stored.
+    ///
+    /// The averages are kept, so the next results continue from the forced value.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    ///
+    /// let mvc = MVCompression::new();
+    /// assert_eq!(mvc.force_value(1_000_000), 200);
+    /// assert_eq!(mvc.get_compression_value(), 200);
+    /// ```
+    pub fn force_value(&self, value: i32) -> i32 {
+        let value = self.clamp_to_bounds(value);
+        self.compression_value.store(value, Ordering::Relaxed);
+        value
+    }
+
+    fn clamp_to_bounds(&self, value: i32) -> i32 {
+        let min = self.config.min_compression_value;
+        value.clamp(min, self.config.max_compression_value.max(min))
+    }
+
+    /// Moves the learned state toward `other`, e.g. the consensus of a fleet.
+    ///
+    /// `weight` is the share of `other` in the result: `0.0` keeps this state,
+    /// `1.0` adopts `other` (it is clamped to that range). The score is clamped
+    /// to this instance's bounds. If only one side has samples, its averages are
+    /// used. Updates from other threads that race with the merge may be lost.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    ///
+    /// let fleet = MVCompression::new();
+    /// for _ in 0..10 {
+    ///     fleet.update_compression_ratio(1000, 1000);
+    /// }
+    ///
+    /// let node = MVCompression::new();
+    /// node.merge(&fleet.snapshot(), 0.5);
+    /// assert_eq!(node.get_compression_value(), -60);
+    /// assert_eq!(node.get_uncompressed_average(), 1000);
+    /// ```
+    pub fn merge(&self, other: &Snapshot, weight: f64) {
+        let weight = if weight.is_nan() { 0.0 } else { weight.clamp(0.0, 1.0) };
+        let current = self.snapshot();
+        let Some(mut merged) = Snapshot::combine([(current, 1.0 - weight), (*other, weight)]) else {
+            return;
+        };
+        merged.compression_value = merged.compression_value.clamp(
+            self.config.min_compression_value.min(current.compression_value),
+            self.config.max_compression_value.max(current.compression_value),
+        );
+        self.restore(&merged);
+    }
+
+    /// Returns statistics over the sliding window of recent outcomes.
+    ///
+    /// Returns `None` unless [`Statistics::Window`] is configured and at least
+    /// one outcome has been recorded.
+    pub fn window_stats(&self) -> Option<WindowStats> {
+        match self.config.statistics {
+            Statistics::MovingAverage => None,
+            Statistics::Window { size, .. } => self.window.stats(size),
+        }
+    }
+
+    /// Returns histograms of the ratios and uncompressed sizes of all blocks
+    /// reported since construction or the last reset.
+    ///
+    /// Returns `None` unless [`MVCompressionConfig::histograms`] is enabled.
+    /// Blocks are counted even while a [`PolicyMode`] override ignores them.
+    #[cfg(feature = "std")]
+    pub fn histograms(&self) -> Option<Histograms> {
+        self.config
+            .histograms
+            .then(|| self.histograms.get_or_init(AtomicHistograms::new).load())
+    }
+
+    /// Returns the number of regime changes detected since construction.
+    ///
+    /// Always zero unless [`MVCompressionConfig::regime_detection`] is set.
+    pub fn regime_changes(&self) -> u64 {
+        self.regime_changes.load(Ordering::Relaxed)
     }
 
     /// Returns the current compression value for debugging or monitoring purposes.
@@ -332,6 +1223,9 @@ impl MVCompression {
     /// - Positive values: bias toward skipping compression
     /// - Zero: neutral (skip logic activated but no strong bias)
     /// 
+    /// When decay is configured, the returned value reflects decay up to the
+    /// last decision or update, not up to the moment of the call.
+    /// 
     /// # Thread Safety
     /// Uses atomic load with relaxed ordering for best performance.
     /// 
@@ -352,14 +1246,14 @@ impl MVCompression {
     /// Returns the current compressed size moving average.
     /// 
     /// This value represents the smoothed average of compressed block sizes
-    /// processed by the algorithm. Note that due to the bit-shifting smoothing,
-    /// this value is approximately 1/8th of the actual average size.
+    /// processed by the algorithm, rounded to the nearest byte. The average is
+    /// seeded by the first block, so it is zero only before any block was seen.
     /// 
     /// # Thread Safety
     /// Uses atomic load with relaxed ordering for best performance.
     /// 
     /// # Returns
-    /// Current compressed size moving average (bit-shifted for smoothing)
+    /// Current compressed size moving average in bytes
     /// 
     /// # Examples
     /// ```rust
@@ -369,17 +1263,35 @@ impl MVCompression {
     /// assert_eq!(mvc.get_compressed_average(), 0); // Initially zero
     /// 
     /// mvc.update_compression_ratio(800, 1000);
-    /// assert_eq!(mvc.get_compressed_average(), 100); // 800 >> 3 = 100
+    /// assert_eq!(mvc.get_compressed_average(), 800); // Seeded by the first block
+    /// 
+    /// mvc.update_compression_ratio(0, 1000);
+    /// assert_eq!(mvc.get_compressed_average(), 700); // 800 + (0 - 800) / 8
     /// ```
     pub fn get_compressed_average(&self) -> usize {
-        self.compressed_size_moving_average.load(Ordering::Relaxed)
+        self.compressed_size_moving_average.get_rounded()
+    }
+
+    /// Returns the compressed size moving average with its fractional part.
+    /// 
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    /// 
+    /// let mvc = MVCompression::new();
+    /// mvc.update_compression_ratio(1, 64);
+    /// mvc.update_compression_ratio(4, 64);
+    /// assert_eq!(mvc.get_compressed_average_precise(), 1.375);
+    /// ```
+    pub fn get_compressed_average_precise(&self) -> f64 {
+        self.compressed_size_moving_average.get_f64()
     }
 
     /// Returns the current uncompressed size moving average.
     /// 
     /// This value represents the smoothed average of uncompressed block sizes
-    /// processed by the algorithm. Note that due to the bit-shifting smoothing,
-    /// this value is approximately 1/8th of the actual average size.
+    /// processed by the algorithm, rounded to the nearest byte. The average is
+    /// seeded by the first block, so it is zero only before any block was seen.
     /// 
     /// Used internally by `should_skip_compression` to determine if an incoming
     /// block size is within the expected range.
@@ -388,7 +1300,7 @@ impl MVCompression {
     /// Uses atomic load with relaxed ordering for best performance.
     /// 
     /// # Returns
-    /// Current uncompressed size moving average (bit-shifted for smoothing)
+    /// Current uncompressed size moving average in bytes
     /// 
     /// # Examples
     /// ```rust
@@ -398,27 +1310,178 @@ impl MVCompression {
     /// assert_eq!(mvc.get_uncompressed_average(), 0); // Initially zero
     /// 
     /// mvc.update_compression_ratio(800, 1000);
-    /// assert_eq!(mvc.get_uncompressed_average(), 125); // 1000 >> 3 = 125
+    /// assert_eq!(mvc.get_uncompressed_average(), 1000); // Seeded by the first block
     /// ```
     pub fn get_uncompressed_average(&self) -> usize {
-        self.uncompressed_size_moving_average.load(Ordering::Relaxed)
+        self.uncompressed_size_moving_average.get_rounded()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::thread;
-    use std::sync::Arc;
+    /// Returns the uncompressed size moving average with its fractional part.
+    pub fn get_uncompressed_average_precise(&self) -> f64 {
+        self.uncompressed_size_moving_average.get_f64()
+    }
 
-    #[test]
-    fn test_new_mvcompression() {
+    /// Returns the moving average of per-block compression ratios.
+    /// 
+    /// Unlike `get_compressed_average() / get_uncompressed_average()`, this weighs
+    /// every block equally regardless of its size. Zero before any block was seen.
+    /// 
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::MVCompression;
+    /// 
+    /// let mvc = MVCompression::new();
+    /// mvc.update_compression_ratio(16, 64);
+    /// assert_eq!(mvc.get_ratio_average(), 0.25);
+    /// ```
+    pub fn get_ratio_average(&self) -> f64 {
+        self.ratio_moving_average.get_f64()
+    }
+}
+
+/// Fractional bits of the fixed-point numbers used by [`decay_step`].
+const DECAY_ONE: u32 = 62;
+
+/// `2^(-2^-(k + 1))` in `Q62`, for the fractional bits of a decay exponent.
+const DECAY_FACTORS: [u128; 32] = {
+    let mut factors = [0u128; 32];
+    // 2^(-1/2) = sqrt(1/2)
+    factors[0] = (1u128 << (2 * DECAY_ONE - 1)).isqrt();
+    let mut k = 1;
+    while k < factors.len() {
+        factors[k] = (factors[k - 1] << DECAY_ONE).isqrt();
+        k += 1;
+    }
+    factors
+};
+
+/// Returns `value * 2^(-exponent)` for an exponent in `Q32`, rounded down.
+fn exp2_neg(value: u128, exponent: u128) -> u128 {
+    let whole = exponent >> 32;
+    if whole >= 96 {
+        return 0;
+    }
+    let mut value = value >> whole;
+    for (k, factor) in DECAY_FACTORS.iter().enumerate() {
+        if exponent & (1 << (31 - k)) != 0 {
+            value = (value * factor) >> DECAY_ONE;
+        }
+    }
+    value
+}
+
+/// Returns `log2(numerator / denominator)` in `Q32`, rounded down, for
+/// `numerator >= denominator > 0` below `2^64`.
+fn log2_ratio(numerator: u128, denominator: u128) -> u128 {
+    let ratio = (numerator << DECAY_ONE) / denominator;
+    let whole = 127 - ratio.leading_zeros() - DECAY_ONE;
+    let mut y = ratio >> whole;
+    let mut log = u128::from(whole) << 32;
+    for bit in (0..32).rev() {
+        y = (y * y) >> DECAY_ONE;
+        if y >= 2 << DECAY_ONE {
+            y >>= 1;
+            log |= 1 << bit;
+        }
+    }
+    log
+}
+
+/// Moves `value` toward `target` for `elapsed` nanoseconds of decay.
+///
+/// The distance to the target follows `2^(-t / half_life)` exactly and is
+/// rounded to the nearest integer. A score of distance `d` is taken to have
+/// just been rounded to `d`, i.e. to lie at `d + 1/2` on the curve. Returns the
+/// new value and the nanoseconds the curve took to reach it, which never
+/// exceed `elapsed`; the rest is left for the next call, so frequent callers
+/// decay exactly as fast as rare ones. Returns `None` while `elapsed` is too
+/// short to move the value.
+fn decay_step(value: i32, target: i32, elapsed: u64, decay: &DecayConfig) -> Option<(i32, u64)> {
+    let half_life = u64::try_from(decay.half_life.as_nanos()).unwrap_or(u64::MAX);
+    let distance = (i64::from(value) - i64::from(target)).unsigned_abs();
+    if half_life == 0 || distance == 0 {
+        return Some((target, elapsed));
+    }
+
+    // Twice the distance on the curve, `2d + 1`, and where it is after `elapsed`.
+    let doubled = u128::from(2 * distance + 1);
+    let exponent = (u128::from(elapsed) << 32) / u128::from(half_life);
+    let decayed = exp2_neg(doubled << 32, exponent);
+    // Nearest distance, rounding halves down: the smallest `d'` with `2d' + 1 >= decayed`.
+    let new_distance = decayed.saturating_sub(1 << 32).div_ceil(1 << 33) as u64;
+    if new_distance >= distance {
+        return None;
+    }
+
+    let ratio = log2_ratio(doubled, u128::from(2 * new_distance + 1));
+    let consumed = (u128::from(half_life) * ratio) >> 32;
+    let consumed = (consumed as u64).clamp(1, elapsed);
+    // The result lies between `target` and `value`, so it always fits.
+    let new_value = if value > target {
+        i64::from(target) + new_distance as i64
+    } else {
+        i64::from(target) - new_distance as i64
+    };
+    Some((new_value as i32, consumed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::*;
+    use crate::layout::{CacheLine, CachePadded};
+    use crate::ratio::Ratio;
+    use crate::rng::SeededRng;
+    use crate::window::WindowStatistic;
+    #[cfg(not(feature = "std"))]
+    use std::{string::String, vec, vec::Vec};
+    use std::thread;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_new_mvcompression() {
         let mvc = MVCompression::new();
         assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE);
         assert_eq!(mvc.get_compressed_average(), 0);
         assert_eq!(mvc.get_uncompressed_average(), 0);
     }
 
+    #[test]
+    fn test_static_instances() {
+        static DEFAULT: MVCompression = MVCompression::new();
+        static CONFIGURED: MVCompression = MVCompression::with_config(MVCompressionConfig {
+            initial_compression_value: 0,
+            skip_window: SkipWindow::symmetric(Ratio::new(1, 2)),
+            decay: Some(DecayConfig::new(Duration::from_secs(60)).with_target(DecayTarget::Zero)),
+            regime_detection: Some(RegimeDetectionConfig::new()),
+            statistics: Statistics::window(WindowStatistic::Median),
+            ..MVCompressionConfig::new()
+        });
+
+        assert_eq!(DEFAULT.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+        assert_eq!(CONFIGURED.get_compression_value(), 0);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..10 {
+                        CONFIGURED.update_compression_ratio(1000, 1000);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            CONFIGURED.get_compression_value(),
+            40 * NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+        assert!(CONFIGURED.should_skip_compression(1000));
+    }
+
     #[test]
     fn test_default_trait() {
         let mvc = MVCompression::default();
@@ -612,11 +1675,17 @@ mod tests {
     fn test_moving_average_calculation() {
         let mvc = MVCompression::new();
         
-        // Test that moving average calculation is correct
+        // Test that moving average calculation is correct: the first block seeds
+        // the averages, later blocks move them by 1 / 2^SMOOTHING_FACTOR.
         mvc.update_compression_ratio(800, 1000);
         
-        let expected_compressed = 800 >> SMOOTHING_FACTOR;
-        let expected_uncompressed = 1000 >> SMOOTHING_FACTOR;
+        assert_eq!(mvc.get_compressed_average(), 800);
+        assert_eq!(mvc.get_uncompressed_average(), 1000);
+        
+        mvc.update_compression_ratio(0, 200);
+        
+        let expected_compressed = 800 - (800 >> SMOOTHING_FACTOR);
+        let expected_uncompressed = 1000 - ((1000 - 200) >> SMOOTHING_FACTOR);
         
         assert_eq!(mvc.get_compressed_average(), expected_compressed);
         assert_eq!(mvc.get_uncompressed_average(), expected_uncompressed);
@@ -630,11 +1699,62 @@ mod tests {
         mvc.update_compression_ratio(0, 1000);
         assert!(mvc.get_compression_value() < INITIAL_COMPRESSION_VALUE);
         
-        // Test with zero uncompressed size (edge case)
+        // Zero uncompressed size and implausible expansion are rejected
+        // without touching the learned state.
         let mvc2 = MVCompression::new();
+        mvc2.update_compression_ratio(100, 0);
         mvc2.update_compression_ratio(100, 1);
-        // Should handle gracefully without panicking
-        assert!(mvc2.get_compression_value() != INITIAL_COMPRESSION_VALUE);
+        assert_eq!(mvc2.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+        assert_eq!(mvc2.get_compressed_average(), 0);
+        assert_eq!(mvc2.get_uncompressed_average(), 0);
+    }
+
+    #[test]
+    fn test_try_update_rejects_garbage() {
+        let mvc = MVCompression::new();
+        assert_eq!(mvc.try_update(0, 0), Err(UpdateError::EmptyBlock));
+        assert_eq!(
+            mvc.try_update(67, 1),
+            Err(UpdateError::CompressedTooLarge {
+                compressed: 67,
+                limit: 66
+            })
+        );
+        assert_eq!(
+            mvc.try_update(usize::MAX, 1 << 40),
+            Err(UpdateError::CompressedTooLarge {
+                compressed: usize::MAX,
+                limit: (1 << 41) + 64
+            })
+        );
+        assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+
+        // Expansion within the bound is a legitimate (poor) result.
+        assert_eq!(mvc.try_update(66, 1), Ok(()));
+        assert_eq!(mvc.try_update(usize::MAX, usize::MAX), Ok(()));
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + 2 * NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+
+    #[test]
+    fn test_threshold_is_exact_for_large_blocks() {
+        // Above 2^24 f32 rounds 900_000_001 / 1_000_000_000 to exactly 0.9.
+        let uncompressed = 1_000_000_000;
+        let mvc = MVCompression::new();
+        mvc.update_compression_ratio(900_000_000, uncompressed);
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + COMPRESSIBLE_BLOCK_WEIGHT
+        );
+
+        let mvc = MVCompression::new();
+        mvc.update_compression_ratio(900_000_001, uncompressed);
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
     }
 
     #[test]
@@ -642,7 +1762,7 @@ mod tests {
         let mvc = MVCompression::new();
         
         // Test with large values to ensure no overflow
-        let large_size = usize::MAX >> 10; // Large but won't overflow in calculations
+        let large_size = usize::MAX >> 10;
         mvc.update_compression_ratio(large_size / 2, large_size);
         
         // Should handle large values gracefully
@@ -650,6 +1770,88 @@ mod tests {
         assert!(mvc.get_uncompressed_average() > 0);
     }
 
+    #[test]
+    fn test_extreme_sizes_do_not_overflow() {
+        let mvc = MVCompression::new();
+        for _ in 0..30 {
+            mvc.update_compression_ratio(usize::MAX, usize::MAX);
+        }
+        assert!(mvc.get_compression_value() > 0);
+        // The averages saturate well below usize::MAX; the window bound saturates too.
+        assert!(mvc.should_skip_compression(0));
+        assert!(!mvc.should_skip_compression(usize::MAX));
+
+        let config = MVCompressionConfig {
+            skip_window: SkipWindow::new(Ratio::new(1, 1), Ratio::new(u64::MAX, 1)),
+            ..MVCompressionConfig::default()
+        };
+        let mvc = MVCompression::with_config(config);
+        for _ in 0..30 {
+            mvc.update_compression_ratio(usize::MAX, usize::MAX);
+        }
+        assert!(mvc.should_skip_compression(usize::MAX));
+    }
+
+    #[test]
+    fn test_extreme_weights_saturate() {
+        let config = MVCompressionConfig {
+            non_compressible_block_weight: i32::MAX,
+            compressible_block_weight: i32::MIN,
+            skip_compression_block_weight: i32::MIN,
+            max_compression_value: i32::MAX,
+            min_compression_value: i32::MIN,
+            ..MVCompressionConfig::default()
+        };
+        let mvc = MVCompression::with_config(config);
+        mvc.update_compression_ratio(1000, 1000);
+        mvc.update_compression_ratio(1000, 1000);
+        assert_eq!(mvc.get_compression_value(), i32::MAX);
+
+        assert!(mvc.should_skip_compression(1000));
+        assert_eq!(mvc.get_compression_value(), -1);
+        mvc.update_compression_ratio(0, 1000);
+        mvc.update_compression_ratio(0, 1000);
+        assert_eq!(mvc.get_compression_value(), i32::MIN);
+    }
+
+    #[test]
+    fn test_symmetric_skip_window() {
+        let config = MVCompressionConfig {
+            skip_window: SkipWindow::symmetric(Ratio::new(1, 4)),
+            ..MVCompressionConfig::default()
+        };
+        let mvc = MVCompression::with_config(config);
+        for _ in 0..100 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        assert!(mvc.should_skip_compression(750));
+        assert!(mvc.should_skip_compression(1250));
+        assert!(!mvc.should_skip_compression(749));
+        assert!(!mvc.should_skip_compression(1251));
+        assert!(!mvc.should_skip_compression(0));
+    }
+
+    #[test]
+    fn test_decay_between_extreme_bounds() {
+        let clock = ManualClock::new();
+        let config = MVCompressionConfig {
+            initial_compression_value: i32::MIN,
+            min_compression_value: i32::MIN,
+            max_compression_value: i32::MAX,
+            non_compressible_block_weight: i32::MAX,
+            decay: Some(DecayConfig::new(Duration::from_secs(1))),
+            ..MVCompressionConfig::default()
+        };
+        let mvc = MVCompression::builder().config(config).clock(&clock).build();
+        mvc.update_compression_ratio(1000, 1000);
+        mvc.update_compression_ratio(1000, 1000);
+        assert_eq!(mvc.get_compression_value(), i32::MAX - 1);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!mvc.should_skip_compression(usize::MAX));
+        assert_eq!(mvc.get_compression_value(), -1);
+    }
+
     #[test]
     fn test_sequential_behavior_simulation() {
         let mvc = MVCompression::new();
@@ -674,4 +1876,738 @@ mod tests {
         assert!(compress_count > 0, "Should have attempted some compressions");
         assert!(mvc.get_compression_value() > INITIAL_COMPRESSION_VALUE);
     }
+
+    fn decaying(half_life: Duration, target: DecayTarget) -> MVCompression<ManualClock> {
+        let config = MVCompressionConfig {
+            decay: Some(DecayConfig::new(half_life).with_target(target)),
+            ..MVCompressionConfig::default()
+        };
+        MVCompression::builder()
+            .config(config)
+            .clock(ManualClock::new())
+            .build()
+    }
+
+    #[test]
+    fn test_with_config_uses_initial_value() {
+        let config = MVCompressionConfig {
+            initial_compression_value: 10,
+            ..MVCompressionConfig::default()
+        };
+        let mvc = MVCompression::with_config(config);
+        assert_eq!(mvc.get_compression_value(), 10);
+        assert_eq!(mvc.config().initial_compression_value, 10);
+    }
+
+    #[test]
+    fn test_decay_halves_distance_per_half_life() {
+        let mvc = decaying(Duration::from_secs(10), DecayTarget::Initial);
+
+        // Drive the score up to +120 (200 above the initial value).
+        for _ in 0..50 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        assert_eq!(mvc.get_compression_value(), 120);
+
+        mvc.clock().advance(Duration::from_secs(10));
+        mvc.should_skip_compression(usize::MAX);
+        assert_eq!(mvc.get_compression_value(), 20);
+
+        mvc.clock().advance(Duration::from_secs(20));
+        mvc.should_skip_compression(usize::MAX);
+        assert_eq!(mvc.get_compression_value(), -55);
+
+        // Half a half-life leaves 25 / sqrt(2) of the distance.
+        mvc.clock().advance(Duration::from_secs(5));
+        mvc.should_skip_compression(usize::MAX);
+        assert_eq!(mvc.get_compression_value(), -62);
+    }
+
+    #[test]
+    fn test_decay_toward_zero_and_never_overshoots() {
+        let mvc = decaying(Duration::from_secs(1), DecayTarget::Zero);
+        mvc.update_compression_ratio(100, 1000);
+        assert_eq!(mvc.get_compression_value(), -90);
+
+        mvc.clock().advance(Duration::from_secs(3600));
+        mvc.update_compression_ratio(100, 1000);
+        assert_eq!(mvc.get_compression_value(), COMPRESSIBLE_BLOCK_WEIGHT);
+    }
+
+    #[test]
+    fn test_decay_accumulates_small_intervals() {
+        let mvc = decaying(Duration::from_secs(10), DecayTarget::Zero);
+        mvc.update_compression_ratio(100, 1000);
+        assert_eq!(mvc.get_compression_value(), -90);
+
+        // Each step alone is too small to move the score, but the elapsed time
+        // must not be lost.
+        for _ in 0..100 {
+            mvc.clock().advance(Duration::from_millis(1));
+            mvc.should_skip_compression(1000);
+        }
+        assert_eq!(mvc.get_compression_value(), -90);
+
+        mvc.clock().advance(Duration::from_millis(9900));
+        mvc.should_skip_compression(1000);
+        assert_eq!(mvc.get_compression_value(), -45);
+    }
+
+    #[test]
+    fn test_frequent_decisions_decay_at_the_configured_rate() {
+        let mvc = decaying(Duration::from_secs(1), DecayTarget::Zero);
+        mvc.force_value(200);
+        mvc.should_skip_compression(1000);
+        for _ in 0..1000 {
+            mvc.clock().advance(Duration::from_millis(1));
+            mvc.decide(1000);
+        }
+        assert_eq!(mvc.get_compression_value(), 100);
+
+        for _ in 0..2000 {
+            mvc.clock().advance(Duration::from_millis(1));
+            mvc.decide(1000);
+        }
+        assert_eq!(mvc.get_compression_value(), 25);
+    }
+
+    #[test]
+    fn test_decay_factors() {
+        let one = 1u128 << 32;
+        for (elapsed, expected) in [(0.0, 1.0), (0.5, 0.5f64.sqrt()), (1.0, 0.5), (2.75, 0.148_650_889_5)] {
+            let factor = exp2_neg(one << 32, (elapsed * one as f64) as u128) as f64 / (one * one) as f64;
+            assert!((factor - expected).abs() < 1e-9, "{elapsed}: {factor}");
+        }
+        assert_eq!(log2_ratio(8, 1), 3 << 32);
+        let log = log2_ratio(201, 101) as f64 / one as f64;
+        assert!((log - (201.0f64 / 101.0).log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_no_decay_without_config() {
+        let mvc = MVCompression::builder().clock(ManualClock::new()).build();
+        mvc.update_compression_ratio(1000, 1000);
+        mvc.clock().advance(Duration::from_secs(3600));
+        mvc.should_skip_compression(1000);
+        assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE + NON_COMPRESSIBLE_BLOCK_WEIGHT);
+    }
+
+    fn probing(probe_rate: u32, seed: u64) -> MVCompression<ManualClock, SeededRng> {
+        let config = MVCompressionConfig {
+            probe_rate,
+            ..MVCompressionConfig::default()
+        };
+        MVCompression::builder()
+            .config(config)
+            .clock(ManualClock::new())
+            .rng(SeededRng::new(seed))
+            .build()
+    }
+
+    /// Runs the incompressible-stream simulation and records each decision.
+    fn decision_sequence<C: Clock, R: Rng, L: Layout>(
+        mvc: &MVCompression<C, R, L>,
+        blocks: usize,
+    ) -> String {
+        (0..blocks)
+            .map(|_| {
+                if mvc.should_skip_compression(1000) {
+                    'S'
+                } else {
+                    mvc.update_compression_ratio(1000, 1000);
+                    'C'
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_decision_sequence_without_probing() {
+        let mvc = probing(0, 0);
+        assert_eq!(
+            decision_sequence(&mvc, 30),
+            "CCCCCCCCCCCCCCCCCCCCCSSSSCSSSS"
+        );
+    }
+
+    #[test]
+    fn test_padded_layout_makes_identical_decisions() {
+        let padded = MVCompression::builder()
+            .config(MVCompressionConfig {
+                probe_rate: 3,
+                ..MVCompressionConfig::default()
+            })
+            .clock(ManualClock::new())
+            .rng(SeededRng::new(11))
+            .layout::<CachePadded>()
+            .build();
+        assert_eq!(
+            decision_sequence(&padded, 40),
+            decision_sequence(&probing(3, 11), 40)
+        );
+        assert!(core::mem::size_of_val(&padded) >= 7 * core::mem::align_of::<CacheLine>());
+    }
+
+    #[test]
+    fn test_probing_is_deterministic_for_a_seed() {
+        assert_eq!(
+            decision_sequence(&probing(3, 11), 40),
+            "CCCCCCCCCCCCCCCCCCCCCCSCSSSCSSSSSSSCSSSS"
+        );
+
+        let first = decision_sequence(&probing(3, 11), 200);
+        let second = decision_sequence(&probing(3, 11), 200);
+        assert_eq!(first, second);
+
+        // Probes turn some of the skips into compression attempts.
+        let unprobed = decision_sequence(&probing(0, 11), 200);
+        let skips = |sequence: &str| sequence.matches('S').count();
+        assert!(skips(&first) < skips(&unprobed));
+    }
+
+    #[test]
+    fn test_probe_rate_one_never_skips() {
+        let mvc = probing(1, 5);
+        assert!(!decision_sequence(&mvc, 100).contains('S'));
+        assert!(mvc.get_compression_value() > 0);
+    }
+
+    fn detecting() -> MVCompression {
+        MVCompression::with_config(MVCompressionConfig {
+            regime_detection: Some(RegimeDetectionConfig::default()),
+            ..MVCompressionConfig::default()
+        })
+    }
+
+    /// Counts the poor blocks needed before an incompressible stream is skipped.
+    fn blocks_until_skip(mvc: &MVCompression) -> usize {
+        (1..=200)
+            .find(|_| {
+                if mvc.should_skip_compression(1000) {
+                    true
+                } else {
+                    mvc.update_compression_ratio(1000, 1000);
+                    false
+                }
+            })
+            .unwrap_or(usize::MAX)
+    }
+
+    #[test]
+    fn test_regime_change_relearns_quickly() {
+        let plain = MVCompression::new();
+        let detecting = detecting();
+        for mvc in [&plain, &detecting] {
+            for _ in 0..50 {
+                mvc.update_compression_ratio(300, 1000);
+            }
+            assert_eq!(mvc.get_compression_value(), MIN_COMPRESSION_VALUE);
+        }
+
+        assert!(blocks_until_skip(&plain) > 70);
+        assert_eq!(blocks_until_skip(&detecting), 2);
+        assert_eq!(plain.regime_changes(), 0);
+        assert_eq!(detecting.regime_changes(), 1);
+        // Averages were re-seeded from the encrypted blocks.
+        assert_eq!(detecting.get_compressed_average(), detecting.get_uncompressed_average());
+    }
+
+    #[test]
+    fn test_regime_change_back_to_compressible() {
+        let mvc = detecting();
+        for _ in 0..80 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        assert_eq!(mvc.get_compression_value(), MAX_COMPRESSION_VALUE);
+
+        mvc.update_compression_ratio(200, 1000);
+        assert_eq!(mvc.regime_changes(), 1);
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+
+    #[test]
+    fn test_regime_change_callback() {
+        static CHANGES: AtomicU64 = AtomicU64::new(0);
+        fn record(change: RegimeChange) {
+            assert_eq!(change.shift, RegimeShift::LessCompressible);
+            assert!(change.reference_ratio < 0.3);
+            assert_eq!(change.ratio, 1.0);
+            assert_eq!(change.previous_value, MIN_COMPRESSION_VALUE);
+            assert_eq!(change.new_value, 0);
+            CHANGES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            regime_detection: Some(RegimeDetectionConfig {
+                on_change: Some(record),
+                ..RegimeDetectionConfig::default()
+            }),
+            ..MVCompressionConfig::default()
+        });
+        for _ in 0..30 {
+            mvc.update_compression_ratio(256, 1024);
+        }
+        for _ in 0..5 {
+            mvc.update_compression_ratio(1024, 1024);
+        }
+        assert_eq!(CHANGES.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_observer_sees_regime_changes() {
+        let recording = Recording::default();
+        let mvc = MVCompression::builder()
+            .config(MVCompressionConfig {
+                regime_detection: Some(RegimeDetectionConfig::default()),
+                ..MVCompressionConfig::default()
+            })
+            .observer(&recording)
+            .build();
+        for _ in 0..30 {
+            mvc.update_compression_ratio(256, 1024);
+        }
+        recording.0.lock().unwrap().clear();
+        for _ in 0..5 {
+            mvc.update_compression_ratio(1024, 1024);
+        }
+
+        let events = recording.0.lock().unwrap();
+        let change = events
+            .iter()
+            .find(|event| event.kind == EventKind::RegimeChange)
+            .unwrap();
+        assert_eq!(change.reason, EventReason::RegimeChange);
+        assert_eq!((change.previous_value, change.new_value), (MIN_COMPRESSION_VALUE, 0));
+        assert_eq!(change.uncompressed, Some(1024));
+        assert_eq!(events.iter().filter(|event| event.kind == EventKind::RegimeChange).count(), 1);
+    }
+
+    fn windowed(statistic: WindowStatistic) -> MVCompression {
+        MVCompression::with_config(MVCompressionConfig {
+            statistics: Statistics::Window {
+                size: 10,
+                statistic,
+            },
+            ..MVCompressionConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_window_median_ignores_outliers() {
+        let ema = MVCompression::new();
+        let median = windowed(WindowStatistic::Median);
+        for mvc in [&ema, &median] {
+            for i in 0..80 {
+                // Mostly incompressible, with a highly compressible block every fourth.
+                let compressed = if i % 4 == 0 { 100 } else { 1000 };
+                mvc.update_compression_ratio(compressed, 1000);
+            }
+        }
+        // The moving-average path counts every compressible block against skipping.
+        assert!(ema.get_compression_value() < 0);
+        // The median path classifies each block by the window median (1.0).
+        assert_eq!(median.get_compression_value(), MAX_COMPRESSION_VALUE);
+        assert_eq!(median.window_stats().unwrap().median_ratio, 1.0);
+        assert_eq!(ema.window_stats(), None);
+    }
+
+    #[test]
+    fn test_window_size_statistic_drives_skip_window() {
+        let mvc = windowed(WindowStatistic::P90);
+        for i in 0..60 {
+            let uncompressed = if i % 10 == 0 { 4000 } else { 1000 };
+            mvc.update_compression_ratio(uncompressed, uncompressed);
+        }
+        let stats = mvc.window_stats().unwrap();
+        assert_eq!(stats.p90_size, 1000);
+        assert_eq!(stats.median_size, 1000);
+        assert!(mvc.get_compression_value() > 0);
+
+        // The skip window is centered on the p90 size, not a mean dragged up by outliers.
+        assert!(mvc.should_skip_compression(1250));
+        assert!(!mvc.should_skip_compression(1300));
+    }
+
+    #[test]
+    fn test_small_block_averages_are_precise() {
+        let mvc = MVCompression::new();
+        for i in 0..1000 {
+            // 64-byte messages compressing to 5, 6 or 7 bytes.
+            mvc.update_compression_ratio(5 + i % 3, 64);
+        }
+        assert_eq!(mvc.get_uncompressed_average(), 64);
+        assert_eq!(mvc.get_uncompressed_average_precise(), 64.0);
+        assert_eq!(mvc.get_compressed_average(), 6);
+        assert!((mvc.get_compressed_average_precise() - 6.0).abs() < 0.5);
+        assert!((mvc.get_ratio_average() - 6.0 / 64.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_blocks_smaller_than_smoothing_divisor_are_counted() {
+        let mvc = MVCompression::new();
+        for _ in 0..100 {
+            mvc.update_compression_ratio(3, 7);
+        }
+        assert_eq!(mvc.get_compressed_average(), 3);
+        assert_eq!(mvc.get_uncompressed_average(), 7);
+        assert!((mvc.get_ratio_average() - 3.0 / 7.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_merge_blends_toward_snapshot() {
+        let source = MVCompression::new();
+        for _ in 0..100 {
+            source.update_compression_ratio(1000, 1000);
+        }
+        let consensus = source.snapshot();
+
+        let node = MVCompression::new();
+        node.update_compression_ratio(100, 2000);
+        node.merge(&consensus, 0.0);
+        assert_eq!(node.get_compression_value(), INITIAL_COMPRESSION_VALUE + COMPRESSIBLE_BLOCK_WEIGHT);
+        assert_eq!(node.get_uncompressed_average(), 2000);
+
+        node.merge(&consensus, 0.25);
+        assert_eq!(node.get_uncompressed_average(), 1750);
+        assert!((node.get_ratio_average() - (0.75 * 0.05 + 0.25)).abs() < 0.001);
+
+        // Adopting the consensus makes a fresh node decide like the fleet.
+        node.merge(&consensus, f64::INFINITY);
+        assert_eq!(node.snapshot().compression_value, MAX_COMPRESSION_VALUE);
+        assert!(node.should_skip_compression(1000));
+    }
+
+    #[test]
+    fn test_merge_clamps_foreign_scores() {
+        let strict = MVCompression::with_config(MVCompressionConfig {
+            max_compression_value: 50,
+            ..MVCompressionConfig::default()
+        });
+        let foreign = Snapshot {
+            compression_value: 1000,
+            compressed_average: 0.0,
+            uncompressed_average: 0.0,
+            ratio_average: 0.0,
+            regime_changes: 0,
+        };
+        strict.merge(&foreign, 1.0);
+        assert_eq!(strict.get_compression_value(), 50);
+        assert_eq!(strict.get_uncompressed_average(), 0);
+
+        strict.merge(&foreign, f64::NAN);
+        assert_eq!(strict.get_compression_value(), 50);
+    }
+
+    #[test]
+    fn test_reset_clears_learned_state() {
+        let clock = ManualClock::new();
+        let mvc = MVCompression::builder()
+            .config(MVCompressionConfig {
+                statistics: Statistics::window(WindowStatistic::Median),
+                regime_detection: Some(RegimeDetectionConfig::default()),
+                decay: Some(DecayConfig::new(Duration::from_secs(10))),
+                ..MVCompressionConfig::default()
+            })
+            .clock(&clock)
+            .build();
+        for _ in 0..50 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        assert!(mvc.window_stats().is_some());
+
+        mvc.reset();
+        assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+        assert_eq!(mvc.get_uncompressed_average(), 0);
+        assert!(mvc.window_stats().is_none());
+
+        // Decay measures from the first observation after the reset.
+        clock.advance(Duration::from_secs(3600));
+        mvc.force_value(100);
+        assert!(!mvc.should_skip_compression(usize::MAX));
+        assert_eq!(mvc.get_compression_value(), 100);
+    }
+
+    #[test]
+    fn test_reset_to_and_force_value_clamp() {
+        let mvc = MVCompression::new();
+        let seed = Snapshot {
+            compression_value: -1000,
+            compressed_average: 300.0,
+            uncompressed_average: 1000.0,
+            ratio_average: 0.3,
+            regime_changes: 7,
+        };
+        mvc.reset_to(&seed);
+        assert_eq!(mvc.get_compression_value(), MIN_COMPRESSION_VALUE);
+        assert_eq!(mvc.get_compressed_average(), 300);
+        assert_eq!(mvc.regime_changes(), 0);
+
+        assert_eq!(mvc.force_value(i32::MIN), MIN_COMPRESSION_VALUE);
+        assert_eq!(mvc.force_value(42), 42);
+        // Forcing keeps the averages.
+        assert_eq!(mvc.get_uncompressed_average(), 1000);
+    }
+
+    #[test]
+    fn test_forced_modes_pin_decisions() {
+        let mvc = MVCompression::builder().rng(SeededRng::new(7)).build();
+        for _ in 0..100 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        let learned = mvc.snapshot();
+
+        mvc.set_mode(PolicyMode::AlwaysCompress {
+            record_outcomes: false,
+        });
+        for _ in 0..100 {
+            assert!(!mvc.should_skip_compression(1000));
+            mvc.update_compression_ratio(0, 1000);
+        }
+        // Neither decisions nor ignored outcomes moved the learned state.
+        assert_eq!(mvc.snapshot(), learned);
+
+        let previous = mvc.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: true,
+        });
+        assert_eq!(
+            previous,
+            PolicyMode::AlwaysCompress {
+                record_outcomes: false
+            }
+        );
+        assert!((0..100).all(|_| mvc.should_skip_compression(1000)));
+        assert_eq!(mvc.get_compression_value(), MAX_COMPRESSION_VALUE);
+        mvc.update_compression_ratio(0, 1000);
+        assert_eq!(
+            mvc.get_compression_value(),
+            MAX_COMPRESSION_VALUE + COMPRESSIBLE_BLOCK_WEIGHT
+        );
+        assert!(mvc.try_update(1, 0).is_err());
+
+        mvc.set_mode(PolicyMode::Adaptive);
+        assert_eq!(mvc.mode(), PolicyMode::Adaptive);
+    }
+
+    #[test]
+    fn test_batches_respect_mode() {
+        let mvc = MVCompression::new();
+        mvc.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: false,
+        });
+        {
+            let mut recorder = mvc.local_recorder(4);
+            for _ in 0..10 {
+                recorder.update_compression_ratio(0, 1000);
+            }
+        }
+        assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+        assert_eq!(mvc.get_uncompressed_average(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_histograms_are_opt_in_and_see_every_block() {
+        assert!(MVCompression::new().histograms().is_none());
+
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            histograms: true,
+            ..MVCompressionConfig::default()
+        });
+        assert!(!mvc.supports_batching());
+        {
+            let mut recorder = mvc.local_recorder(16);
+            for _ in 0..3 {
+                recorder.update_compression_ratio(250, 1000);
+            }
+            recorder.update_compression_ratio(64_000, 64_000);
+        }
+        let histograms = mvc.histograms().unwrap();
+        assert_eq!(histograms.ratio.count(), 4);
+        assert_eq!(histograms.ratio.fraction_below(0.5), 0.75);
+        assert!(histograms.block_size.quantile(1.0).unwrap() > 60_000.0);
+
+        mvc.reset();
+        assert_eq!(mvc.histograms().unwrap().ratio.count(), 0);
+    }
+
+    #[test]
+    fn test_stats_count_decisions_and_savings() {
+        let uncounted = MVCompression::new();
+        uncounted.should_skip_compression(1000);
+        uncounted.update_compression_ratio(400, 1000);
+        assert_eq!(uncounted.stats().decisions(), 0);
+        assert_eq!(uncounted.stats().outcomes, 0);
+
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            counters: true,
+            ..MVCompressionConfig::default()
+        });
+        mvc.should_skip_compression(1000);
+        mvc.update_compression_ratio(400, 1000);
+        mvc.update_compression_ratio(1200, 1000);
+        let _ = mvc.try_update(10, 0);
+        mvc.set_mode(PolicyMode::NeverCompress { record_outcomes: false });
+        mvc.should_skip_compression(1000);
+        {
+            let mut recorder = mvc.local_recorder(4);
+            recorder.update_compression_ratio(100, 1000);
+            recorder.update_compression_ratio(900, 1000);
+        }
+
+        let stats = mvc.stats();
+        assert_eq!((stats.attempted, stats.skipped), (1, 1));
+        assert_eq!(stats.decisions(), 2);
+        // Rejected results are not counted; ignored ones and expansions are.
+        assert_eq!(stats.outcomes, 4);
+        assert_eq!(stats.bytes_saved, 600 + 900 + 100);
+
+        mvc.reset();
+        assert_eq!(mvc.stats().outcomes, 4);
+    }
+
+    #[test]
+    fn test_hysteresis_keeps_state_inside_band() {
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            hysteresis: Hysteresis::symmetric(20),
+            ..MVCompressionConfig::default()
+        });
+        mvc.update_compression_ratio(1000, 1000);
+        let decide = |value| {
+            mvc.force_value(value);
+            mvc.should_skip_compression(1000)
+        };
+
+        // Compressing until the score rises above +20.
+        assert!(!decide(10));
+        assert!(!decide(20));
+        assert!(decide(21));
+        // Skipping until it falls below -20.
+        assert!(decide(0));
+        assert!(decide(-20));
+        assert!(!decide(-21));
+        assert!(!decide(0));
+
+        let stats = mvc.stats();
+        assert_eq!(stats.skip_state, SkipState::Compressing);
+        assert_eq!(stats.skip_entered, 1);
+        assert_eq!(stats.skip_exited, 1);
+        assert_eq!(stats.transitions(), 2);
+    }
+
+    #[test]
+    fn test_default_hysteresis_flips_at_zero() {
+        let mvc = MVCompression::new();
+        mvc.update_compression_ratio(1000, 1000);
+        for value in [1, 0, 1, 0] {
+            mvc.force_value(value);
+            assert_eq!(mvc.should_skip_compression(1000), value > 0);
+        }
+        assert_eq!(mvc.stats().transitions(), 4);
+
+        mvc.force_value(50);
+        mvc.should_skip_compression(1000);
+        mvc.reset();
+        assert_eq!(mvc.stats().skip_state, SkipState::Compressing);
+    }
+
+    #[derive(Default)]
+    struct Recording(std::sync::Mutex<Vec<Event>>);
+
+    impl Observer for Recording {
+        fn on_event(&self, event: &Event) {
+            self.0.lock().unwrap().push(*event);
+        }
+    }
+
+    impl Recording {
+        fn kinds(&self) -> Vec<EventKind> {
+            self.0.lock().unwrap().iter().map(|event| event.kind).collect()
+        }
+    }
+
+    #[test]
+    fn test_observer_sees_transitions_and_bounds() {
+        let recording = Recording::default();
+        let mvc = MVCompression::builder().observer(&recording).build();
+        for _ in 0..70 {
+            mvc.update_compression_ratio(1000, 1000);
+        }
+        assert_eq!(recording.kinds(), [EventKind::ReachedMax]);
+        let reached = recording.0.lock().unwrap()[0];
+        assert_eq!(reached.reason, EventReason::Outcome);
+        assert_eq!(reached.previous_value, MAX_COMPRESSION_VALUE - NON_COMPRESSIBLE_BLOCK_WEIGHT);
+        assert_eq!(reached.new_value, MAX_COMPRESSION_VALUE);
+        assert_eq!(reached.compressed, Some(1000));
+
+        assert!(mvc.should_skip_compression(1000));
+        mvc.force_value(-1);
+        assert!(!mvc.should_skip_compression(1000));
+        mvc.force_value(MIN_COMPRESSION_VALUE + 1);
+        mvc.update_compression_ratio(0, 1000);
+        assert_eq!(
+            recording.kinds(),
+            [
+                EventKind::ReachedMax,
+                EventKind::SkipStarted,
+                EventKind::SkipStopped,
+                EventKind::ReachedMin
+            ]
+        );
+        let started = recording.0.lock().unwrap()[1];
+        assert_eq!(started.reason, EventReason::Decision);
+        assert_eq!(started.uncompressed, Some(1000));
+        assert_eq!(started.compressed, None);
+    }
+
+    #[test]
+    fn test_observer_sees_size_outliers() {
+        let recording = Recording::default();
+        let mvc = MVCompression::builder().observer(&recording).build();
+        mvc.update_compression_ratio(500, 1000);
+        mvc.update_compression_ratio(2000, 4000);
+        mvc.update_compression_ratio(100, 400);
+        assert!(recording.kinds().is_empty());
+
+        mvc.update_compression_ratio(3000, 6000);
+        mvc.update_compression_ratio(10, 100);
+        assert_eq!(recording.kinds(), [EventKind::SizeOutlier, EventKind::SizeOutlier]);
+
+        let quiet = MVCompression::builder()
+            .config(MVCompressionConfig {
+                size_outlier_factor: 0,
+                ..MVCompressionConfig::default()
+            })
+            .observer(&recording)
+            .build();
+        quiet.update_compression_ratio(1, 1);
+        quiet.update_compression_ratio(1, 1_000_000);
+        assert_eq!(recording.kinds().len(), 2);
+    }
+
+    #[test]
+    fn test_decisions_report_reasons() {
+        let mvc = MVCompression::builder().rng(SeededRng::new(1)).build();
+        assert_eq!(mvc.decide(1000).reason, DecisionReason::Compressing);
+        mvc.update_compression_ratio(1000, 1000);
+        mvc.force_value(100);
+        assert_eq!(mvc.decide(1000), Decision::skip(DecisionReason::Skipped));
+        assert_eq!(mvc.decide(5000), Decision::compress(DecisionReason::OutsideWindow));
+        mvc.set_mode(PolicyMode::AlwaysCompress {
+            record_outcomes: true,
+        });
+        assert_eq!(mvc.decide(1000), Decision::compress(DecisionReason::Overridden));
+
+        let probing = MVCompression::builder()
+            .config(MVCompressionConfig {
+                probe_rate: 1,
+                ..MVCompressionConfig::default()
+            })
+            .build();
+        probing.update_compression_ratio(1000, 1000);
+        probing.force_value(100);
+        assert_eq!(probing.decide(1000).reason, DecisionReason::Probe);
+    }
 }
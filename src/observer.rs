@@ -0,0 +1,257 @@
+//! Callbacks for notable decisions and state changes.
+//!
+//! An [`Observer`] is injected through
+//! [`MVCompressionBuilder::observer`](crate::MVCompressionBuilder::observer) and
+//! receives an [`Event`] whenever the instance starts or stops skipping, its
+//! score reaches a bound, a regime change is detected, or a block is far larger
+//! or smaller than usual.
+//! Observers run synchronously on the thread that caused the event, so they
+//! should be cheap (e.g. bump a counter or enqueue the event).
+//!
+//! The default [`NoopObserver`] sets [`Observer::ENABLED`] to false, which
+//! removes event detection from the compiled code entirely.
+//!
+//! Observers that return true from [`Observer::wants_trace`] additionally
+//! receive a [`TraceEntry`] for every decision and reported result, e.g. a
+//! `TraceRecorder` (with the `std` feature). A pair of observers
+//! `(A, B)` is an observer that forwards to both.
+
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use crate::decision::Decision;
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The skip state switched from compressing to skipping.
+    SkipStarted,
+    /// The skip state switched from skipping back to compressing.
+    SkipStopped,
+    /// The score reached [`MVCompressionConfig::max_compression_value`](crate::MVCompressionConfig::max_compression_value).
+    ReachedMax,
+    /// The score reached [`MVCompressionConfig::min_compression_value`](crate::MVCompressionConfig::min_compression_value).
+    ReachedMin,
+    /// A block's uncompressed size differs from the average by more than
+    /// [`MVCompressionConfig::size_outlier_factor`](crate::MVCompressionConfig::size_outlier_factor).
+    SizeOutlier,
+    /// Regime detection saw the ratio distribution shift and jumped the score
+    /// from `previous_value` to `new_value`; the block is the one that
+    /// triggered detection.
+    RegimeChange,
+}
+
+/// The operation during which an event happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventReason {
+    /// A call to [`should_skip_compression`](crate::MVCompression::should_skip_compression)
+    /// or [`decide`](crate::MVCompression::decide).
+    Decision,
+    /// A single reported compression result.
+    Outcome,
+    /// A batch of results merged by a [`LocalRecorder`](crate::LocalRecorder).
+    Batch,
+    /// A detected regime change jumped the score.
+    RegimeChange,
+}
+
+/// A notable decision or state change, passed to [`Observer::on_event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// What happened.
+    pub kind: EventKind,
+    /// The operation that caused it.
+    pub reason: EventReason,
+    /// Score before the operation.
+    pub previous_value: i32,
+    /// Score after the operation.
+    pub new_value: i32,
+    /// Compressed size of the block involved, if it was compressed.
+    pub compressed: Option<usize>,
+    /// Uncompressed size of the block involved; `None` for batches.
+    pub uncompressed: Option<usize>,
+}
+
+/// One decision or reported result, passed to [`Observer::on_trace`].
+///
+/// Timestamps are read from the instance's [`Clock`](crate::Clock) and scores
+/// are the compression value after the operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEntry {
+    /// A call to [`decide`](crate::MVCompression::decide) or
+    /// [`should_skip_compression`](crate::MVCompression::should_skip_compression).
+    Decision {
+        /// Clock reading at the decision.
+        timestamp_nanos: u64,
+        /// Uncompressed size of the block.
+        size: usize,
+        /// The decision and its reason.
+        decision: Decision,
+        /// Score after the decision.
+        score: i32,
+    },
+    /// A single accepted compression result.
+    Outcome {
+        /// Clock reading when the result was applied.
+        timestamp_nanos: u64,
+        /// Compressed size of the block.
+        compressed: usize,
+        /// Uncompressed size of the block.
+        uncompressed: usize,
+        /// Score after the result.
+        score: i32,
+    },
+    /// Results merged by a [`LocalRecorder`](crate::LocalRecorder).
+    Batch {
+        /// Clock reading when the batch was merged.
+        timestamp_nanos: u64,
+        /// Number of blocks in the batch.
+        blocks: u64,
+        /// Number of those blocks that compressed poorly.
+        poorly_compressed: u64,
+        /// Score after the batch.
+        score: i32,
+    },
+}
+
+impl TraceEntry {
+    /// Returns the clock reading of the entry.
+    pub fn timestamp_nanos(&self) -> u64 {
+        match *self {
+            TraceEntry::Decision { timestamp_nanos, .. }
+            | TraceEntry::Outcome { timestamp_nanos, .. }
+            | TraceEntry::Batch { timestamp_nanos, .. } => timestamp_nanos,
+        }
+    }
+
+    /// Returns the score after the entry.
+    pub fn score(&self) -> i32 {
+        match *self {
+            TraceEntry::Decision { score, .. }
+            | TraceEntry::Outcome { score, .. }
+            | TraceEntry::Batch { score, .. } => score,
+        }
+    }
+}
+
+/// Receives [`Event`]s from an instance.
+pub trait Observer {
+    /// Whether the observer wants events at all.
+    ///
+    /// When false, the instance does not detect or build events, so an
+    /// observer that ignores everything costs nothing.
+    const ENABLED: bool = true;
+
+    /// Called once for every event, on the thread that caused it.
+    fn on_event(&self, event: &Event);
+
+    /// Whether [`on_trace`](Self::on_trace) should be called right now.
+    ///
+    /// Checked before every decision and result is traced; the default returns
+    /// false, so observers that do not trace cost nothing.
+    fn wants_trace(&self) -> bool {
+        false
+    }
+
+    /// Called for every decision and accepted result while
+    /// [`wants_trace`](Self::wants_trace) returns true.
+    fn on_trace(&self, _entry: &TraceEntry) {}
+}
+
+impl<T: Observer + ?Sized> Observer for &T {
+    const ENABLED: bool = T::ENABLED;
+
+    fn on_event(&self, event: &Event) {
+        (**self).on_event(event)
+    }
+
+    fn wants_trace(&self) -> bool {
+        (**self).wants_trace()
+    }
+
+    fn on_trace(&self, entry: &TraceEntry) {
+        (**self).on_trace(entry)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Observer + ?Sized> Observer for Arc<T> {
+    const ENABLED: bool = T::ENABLED;
+
+    fn on_event(&self, event: &Event) {
+        (**self).on_event(event)
+    }
+
+    fn wants_trace(&self) -> bool {
+        (**self).wants_trace()
+    }
+
+    fn on_trace(&self, entry: &TraceEntry) {
+        (**self).on_trace(entry)
+    }
+}
+
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    const ENABLED: bool = A::ENABLED || B::ENABLED;
+
+    fn on_event(&self, event: &Event) {
+        if A::ENABLED {
+            self.0.on_event(event);
+        }
+        if B::ENABLED {
+            self.1.on_event(event);
+        }
+    }
+
+    fn wants_trace(&self) -> bool {
+        self.0.wants_trace() || self.1.wants_trace()
+    }
+
+    fn on_trace(&self, entry: &TraceEntry) {
+        if self.0.wants_trace() {
+            self.0.on_trace(entry);
+        }
+        if self.1.wants_trace() {
+            self.1.on_trace(entry);
+        }
+    }
+}
+
+/// The default observer, which ignores every event at no cost.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl Observer for NoopObserver {
+    const ENABLED: bool = false;
+
+    fn on_event(&self, _event: &Event) {}
+}
+
+/// Adapts a closure into an [`Observer`].
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{EventKind, FnObserver, MVCompression};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static STARTED: AtomicUsize = AtomicUsize::new(0);
+///
+/// let mvc = MVCompression::builder()
+///     .observer(FnObserver(|event: &mvcompression::Event| {
+///         if event.kind == EventKind::SkipStarted {
+///             STARTED.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }))
+///     .build();
+/// mvc.force_value(1);
+/// mvc.should_skip_compression(4096);
+/// assert_eq!(STARTED.load(Ordering::Relaxed), 1);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FnObserver<F>(pub F);
+
+impl<F: Fn(&Event)> Observer for FnObserver<F> {
+    fn on_event(&self, event: &Event) {
+        (self.0)(event)
+    }
+}
@@ -0,0 +1,352 @@
+//! Publishing through the `metrics` facade.
+//!
+//! With the `metrics` feature every instance reports to whatever recorder the
+//! application installed with the `metrics` crate:
+//!
+//! | Metric | Type | Labels |
+//! |--------|------|--------|
+//! | `mvcompression_decisions_total` | counter | `decision` (`skip` or `compress`), `reason` |
+//! | `mvcompression_block_ratio` | histogram | |
+//! | `mvcompression_current_score` | gauge | |
+//!
+//! The `reason` label is the snake-case name of the [decision
+//! reason](crate::DecisionReason), e.g. `outside_window`. Every metric
+//! additionally carries a `policy` label when [`MetricsConfig::name`] is set, so
+//! several instances can be told apart. An instance registers its metrics once,
+//! on first use, and keeps the handles; the recorder therefore has to be
+//! installed before the instance is first used. Without the feature the
+//! configuration is accepted but nothing is published.
+//!
+//! None of these names is used by the `exposition` module, so a recorder's
+//! scrape and a rendered exposition can be served from one endpoint without
+//! announcing a family twice.
+
+#[cfg(feature = "metrics")]
+use std::sync::OnceLock;
+#[cfg(feature = "metrics")]
+use std::vec::Vec;
+
+#[cfg(feature = "metrics")]
+use metrics::{Counter, Gauge, Histogram, Label};
+
+#[cfg(feature = "metrics")]
+use crate::decision::{Decision, DecisionReason};
+
+/// Whether and under which name an instance publishes through the `metrics`
+/// facade.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{MVCompression, MVCompressionConfig, MetricsConfig};
+///
+/// static WAL: MVCompression = MVCompression::with_config(MVCompressionConfig {
+///     metrics: MetricsConfig::named("wal"),
+///     ..MVCompressionConfig::new()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// Whether the instance publishes at all.
+    pub enabled: bool,
+    /// Value of the `policy` label; `None` omits the label.
+    pub name: Option<&'static str>,
+}
+
+impl MetricsConfig {
+    /// Publishes without a `policy` label.
+    pub const fn new() -> Self {
+        Self {
+            enabled: true,
+            name: None,
+        }
+    }
+
+    /// Publishes with the `policy` label set to `name`.
+    pub const fn named(name: &'static str) -> Self {
+        Self {
+            enabled: true,
+            name: Some(name),
+        }
+    }
+
+    /// Publishes nothing.
+    pub const fn disabled() -> Self {
+        Self {
+            enabled: false,
+            name: None,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "metrics")]
+const DECISIONS: &str = "mvcompression_decisions_total";
+#[cfg(feature = "metrics")]
+const RATIO: &str = "mvcompression_block_ratio";
+#[cfg(feature = "metrics")]
+const SCORE: &str = "mvcompression_current_score";
+
+/// Every decision reason with the decisions it can lead to, in the order of
+/// [`Handles::decisions`] and as numbered by [`series`].
+#[cfg(feature = "metrics")]
+const DECISION_SERIES: [(DecisionReason, bool); 6] = [
+    (DecisionReason::Overridden, false),
+    (DecisionReason::Overridden, true),
+    (DecisionReason::Compressing, false),
+    (DecisionReason::OutsideWindow, false),
+    (DecisionReason::Probe, false),
+    (DecisionReason::Skipped, true),
+];
+
+/// Metric handles of one instance, registered on first use.
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+pub(crate) struct Publisher {
+    handles: OnceLock<Handles>,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+struct Handles {
+    decisions: [Counter; DECISION_SERIES.len()],
+    ratio: Histogram,
+    score: Gauge,
+}
+
+#[cfg(feature = "metrics")]
+impl Publisher {
+    pub(crate) const fn new() -> Self {
+        Self {
+            handles: OnceLock::new(),
+        }
+    }
+
+    fn handles(&self, config: &MetricsConfig) -> Option<&Handles> {
+        config
+            .enabled
+            .then(|| self.handles.get_or_init(|| Handles::register(config.name)))
+    }
+
+    /// Counts a decision and publishes the score after it.
+    pub(crate) fn decision(&self, config: &MetricsConfig, decision: Decision, score: i32) {
+        if let Some(handles) = self.handles(config) {
+            handles.decisions[series(decision)].increment(1);
+            handles.score.set(score);
+        }
+    }
+
+    /// Records the ratios of `blocks` outcomes and publishes the score after
+    /// them.
+    pub(crate) fn outcomes(&self, config: &MetricsConfig, ratio: f64, blocks: u64, score: i32) {
+        if let Some(handles) = self.handles(config) {
+            handles.ratio.record_many(ratio, blocks as usize);
+            handles.score.set(score);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Handles {
+    fn register(name: Option<&'static str>) -> Self {
+        metrics::describe_counter!(DECISIONS, "Compression decisions, by outcome and reason.");
+        metrics::describe_histogram!(
+            RATIO,
+            "Compressed-to-uncompressed ratio of reported blocks."
+        );
+        metrics::describe_gauge!(SCORE, "Compression value; positive values enable skipping.");
+        let labels = |extra: &[Label]| {
+            name.map(|name| Label::new("policy", name))
+                .into_iter()
+                .chain(extra.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        Self {
+            decisions: DECISION_SERIES.map(|(reason, skip)| {
+                metrics::counter!(
+                    DECISIONS,
+                    labels(&[
+                        Label::new("decision", if skip { "skip" } else { "compress" }),
+                        Label::new("reason", reason_label(reason)),
+                    ])
+                )
+            }),
+            ratio: metrics::histogram!(RATIO, labels(&[])),
+            score: metrics::gauge!(SCORE, labels(&[])),
+        }
+    }
+}
+
+/// Returns the index of the series of `decision` in [`DECISION_SERIES`].
+#[cfg(feature = "metrics")]
+fn series(decision: Decision) -> usize {
+    match decision.reason {
+        DecisionReason::Overridden => usize::from(decision.skip),
+        DecisionReason::Compressing => 2,
+        DecisionReason::OutsideWindow => 3,
+        DecisionReason::Probe => 4,
+        DecisionReason::Skipped => 5,
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn reason_label(reason: DecisionReason) -> &'static str {
+    match reason {
+        DecisionReason::Overridden => "overridden",
+        DecisionReason::Compressing => "compressing",
+        DecisionReason::OutsideWindow => "outside_window",
+        DecisionReason::Probe => "probe",
+        DecisionReason::Skipped => "skipped",
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::config::MVCompressionConfig;
+    use crate::mode::PolicyMode;
+    use crate::mvcompression::MVCompression;
+    use metrics::{HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+    use std::string::String;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps every registered metric, keyed by its name and labels.
+    #[derive(Default)]
+    struct Capture {
+        counters: Mutex<Vec<(String, Arc<AtomicU64>)>>,
+        gauges: Mutex<Vec<(String, Arc<AtomicU64>)>>,
+        histograms: Mutex<Vec<(String, Arc<Samples>)>>,
+    }
+
+    #[derive(Default)]
+    struct Samples(Mutex<Vec<f64>>);
+
+    impl HistogramFn for Samples {
+        fn record(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    fn describe(key: &Key) -> String {
+        let labels: Vec<_> = key
+            .labels()
+            .map(|label| format!("{}={}", label.key(), label.value()))
+            .collect();
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+
+    fn find<T>(metrics: &Mutex<Vec<(String, Arc<T>)>>, key: &str) -> Arc<T> {
+        let metrics = metrics.lock().unwrap();
+        let (_, metric) = metrics.iter().find(|(name, _)| name == key).unwrap();
+        Arc::clone(metric)
+    }
+
+    impl Recorder for Capture {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let counter = Arc::new(AtomicU64::new(0));
+            self.counters
+                .lock()
+                .unwrap()
+                .push((describe(key), Arc::clone(&counter)));
+            Counter::from_arc(counter)
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            let gauge = Arc::new(AtomicU64::new(0));
+            self.gauges
+                .lock()
+                .unwrap()
+                .push((describe(key), Arc::clone(&gauge)));
+            Gauge::from_arc(gauge)
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            let samples = Arc::new(Samples::default());
+            self.histograms
+                .lock()
+                .unwrap()
+                .push((describe(key), Arc::clone(&samples)));
+            Histogram::from_arc(samples)
+        }
+    }
+
+    fn run(config: MetricsConfig, f: impl FnOnce(&MVCompression)) -> Capture {
+        let capture = Capture::default();
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            metrics: config,
+            ..MVCompressionConfig::default()
+        });
+        metrics::with_local_recorder(&capture, || f(&mvc));
+        capture
+    }
+
+    #[test]
+    fn test_decisions_ratios_and_score_are_published() {
+        let capture = run(MetricsConfig::named("wal"), |mvc| {
+            mvc.should_skip_compression(1000);
+            mvc.update_compression_ratio(250, 1000);
+            {
+                let mut recorder = mvc.local_recorder(8);
+                recorder.update_compression_ratio(500, 1000);
+                recorder.update_compression_ratio(500, 1000);
+            }
+            mvc.set_mode(PolicyMode::NeverCompress {
+                record_outcomes: true,
+            });
+            mvc.should_skip_compression(1000);
+        });
+
+        let compressing = find(
+            &capture.counters,
+            "mvcompression_decisions_total{policy=wal,decision=compress,reason=compressing}",
+        );
+        assert_eq!(compressing.load(Ordering::Relaxed), 1);
+        let overridden = find(
+            &capture.counters,
+            "mvcompression_decisions_total{policy=wal,decision=skip,reason=overridden}",
+        );
+        assert_eq!(overridden.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            capture.counters.lock().unwrap().len(),
+            DECISION_SERIES.len()
+        );
+
+        let ratios = find(&capture.histograms, "mvcompression_block_ratio{policy=wal}");
+        assert_eq!(*ratios.0.lock().unwrap(), [0.25, 0.5, 0.5]);
+        let score = find(&capture.gauges, "mvcompression_current_score{policy=wal}");
+        assert_eq!(f64::from_bits(score.load(Ordering::Relaxed)), -110.0);
+    }
+
+    #[test]
+    fn test_every_series_has_its_own_index() {
+        for (index, &(reason, skip)) in DECISION_SERIES.iter().enumerate() {
+            assert_eq!(series(Decision { skip, reason }), index);
+        }
+    }
+
+    #[test]
+    fn test_unnamed_and_disabled_instances() {
+        let capture = run(MetricsConfig::new(), |mvc| {
+            mvc.update_compression_ratio(250, 1000);
+        });
+        find(&capture.histograms, "mvcompression_block_ratio{}");
+
+        let capture = run(MetricsConfig::disabled(), |mvc| {
+            mvc.should_skip_compression(1000);
+            mvc.update_compression_ratio(250, 1000);
+        });
+        assert!(capture.counters.lock().unwrap().is_empty());
+        assert!(capture.histograms.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+//! Compression ratios: exact rational thresholds and fixed-point observations.
+//!
+//! Thresholds are configured as a [`Ratio`] of two integers and compared by
+//! cross-multiplication, so a decision never depends on float rounding (`f32`
+//! cannot even represent block sizes above 2^24 exactly). Observed ratios that
+//! have to be stored or averaged use fixed point with [`RATIO_FRACTION_BITS`]
+//! fractional bits and saturate at `u32::MAX`, so a ratio always fits in half
+//! of an atomic word.
+
+/// Number of fractional bits in a fixed-point ratio.
+pub(crate) const RATIO_FRACTION_BITS: u32 = 16;
+
+/// An exact non-negative ratio `numerator / denominator`.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::Ratio;
+///
+/// let threshold = Ratio::new(9, 10);
+/// assert!(!threshold.is_exceeded_by(900, 1000));
+/// assert!(threshold.is_exceeded_by(901, 1000));
+///
+/// // Exact even where f32 would round both sides to the same value.
+/// assert!(threshold.is_exceeded_by(900_000_001, 1_000_000_000));
+/// ```
+///
+/// Ratios can only be built through [`Ratio::new`], so the denominator is
+/// never zero:
+/// ```compile_fail
+/// use mvcompression::Ratio;
+///
+/// let unbounded = Ratio { numerator: 1, denominator: 0 };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ratio {
+    numerator: u64,
+    /// Never zero.
+    denominator: u64,
+}
+
+impl Ratio {
+    /// Creates the ratio `numerator / denominator`.
+    ///
+    /// # Panics
+    /// Panics if `denominator` is zero.
+    pub const fn new(numerator: u64, denominator: u64) -> Self {
+        assert!(denominator != 0, "ratio denominator must not be zero");
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Returns the numerator of the ratio.
+    pub const fn numerator(&self) -> u64 {
+        self.numerator
+    }
+
+    /// Returns the denominator of the ratio, which is never zero.
+    pub const fn denominator(&self) -> u64 {
+        self.denominator
+    }
+
+    /// Returns true if `compressed / uncompressed` is strictly greater than this ratio.
+    ///
+    /// A zero `uncompressed` size is treated as an infinitely large ratio when
+    /// `compressed` is non-zero.
+    pub fn is_exceeded_by(&self, compressed: usize, uncompressed: usize) -> bool {
+        compressed as u128 * self.denominator as u128
+            > uncompressed as u128 * self.numerator as u128
+    }
+
+    /// Returns true if the fixed-point ratio `ratio` is strictly greater than this ratio.
+    pub(crate) fn is_exceeded_by_fixed(&self, ratio: u64) -> bool {
+        ratio as u128 * self.denominator as u128
+            > (self.numerator as u128) << RATIO_FRACTION_BITS
+    }
+
+    /// Returns `size * self`, rounded down and saturated to `usize::MAX`.
+    pub fn scale(&self, size: usize) -> usize {
+        let scaled = size as u128 * self.numerator as u128 / self.denominator as u128;
+        scaled.min(usize::MAX as u128) as usize
+    }
+
+    /// Returns the ratio as a float, for reporting.
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+/// Converts `numerator / denominator` into fixed point, saturating.
+///
+/// Returns `None` when the ratio is undefined.
+pub(crate) fn fixed_ratio(numerator: usize, denominator: usize) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    let ratio = ((numerator as u128) << RATIO_FRACTION_BITS) / denominator as u128;
+    Some(ratio.min(u32::MAX as u128) as u64)
+}
+
+/// Converts a fixed-point ratio back to a float for reporting.
+pub(crate) fn ratio_to_f32(ratio: u64) -> f32 {
+    ratio as f32 / (1u64 << RATIO_FRACTION_BITS) as f32
+}
+
+/// Converts a float ratio into fixed point, clamping negative values to zero.
+pub(crate) fn ratio_from_f32(value: f32) -> u64 {
+    (value.max(0.0) * (1u64 << RATIO_FRACTION_BITS) as f32) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_ratio_round_trip() {
+        assert_eq!(fixed_ratio(10, 0), None);
+        assert_eq!(fixed_ratio(1, 2), Some(1 << (RATIO_FRACTION_BITS - 1)));
+        assert_eq!(ratio_to_f32(fixed_ratio(3, 4).unwrap()), 0.75);
+        assert_eq!(ratio_from_f32(0.75), fixed_ratio(3, 4).unwrap());
+        assert_eq!(ratio_from_f32(-1.0), 0);
+    }
+
+    #[test]
+    fn test_ratio_comparisons_are_exact() {
+        let threshold = Ratio::new(9, 10);
+        assert!(!threshold.is_exceeded_by(9, 10));
+        assert!(threshold.is_exceeded_by(usize::MAX, usize::MAX - 1));
+        assert!(!threshold.is_exceeded_by(0, 0));
+        assert!(threshold.is_exceeded_by(1, 0));
+        assert_eq!((threshold.numerator(), threshold.denominator()), (9, 10));
+
+        assert!(!threshold.is_exceeded_by_fixed(fixed_ratio(9, 10).unwrap()));
+        assert!(threshold.is_exceeded_by_fixed(fixed_ratio(91, 100).unwrap()));
+    }
+
+    #[test]
+    fn test_ratio_scale_saturates() {
+        assert_eq!(Ratio::new(5, 4).scale(1000), 1250);
+        assert_eq!(Ratio::new(1, 3).scale(10), 3);
+        assert_eq!(Ratio::new(2, 1).scale(usize::MAX), usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "denominator")]
+    fn test_ratio_rejects_zero_denominator() {
+        Ratio::new(1, 0);
+    }
+
+    #[test]
+    fn test_fixed_ratio_saturates() {
+        assert_eq!(fixed_ratio(usize::MAX, 1), Some(u32::MAX as u64));
+    }
+}
@@ -0,0 +1,373 @@
+//! Per-thread batching of compression results.
+//!
+//! Every [`MVCompression::update_compression_ratio`] call performs several
+//! compare-exchange loops on shared atomics. With many threads reporting small
+//! blocks at a high rate those writes dominate. A [`LocalRecorder`] collects
+//! results on its own thread and merges them into the shared instance every
+//! `flush_every` blocks (and when dropped) with a single update per atomic.
+
+use crate::average::MovingAverage;
+use crate::clock::Clock;
+use crate::error::UpdateError;
+use crate::layout::Layout;
+use crate::mvcompression::MVCompression;
+use crate::observer::{NoopObserver, Observer};
+use crate::ratio::fixed_ratio;
+#[cfg(feature = "metrics")]
+use crate::ratio::RATIO_FRACTION_BITS;
+use crate::rng::Rng;
+use crate::stats::saved_bytes;
+
+/// Aggregate of validated compression outcomes awaiting a merge.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct OutcomeBatch {
+    blocks: u64,
+    poorly_compressed: u64,
+    bytes_saved: u64,
+    /// Sums of fixed-point sizes and ratios, so means keep their fraction.
+    compressed_sum: u128,
+    uncompressed_sum: u128,
+    ratio_sum: u128,
+}
+
+impl OutcomeBatch {
+    /// Adds one outcome with a non-zero uncompressed size.
+    pub(crate) fn record(
+        &mut self,
+        compressed: usize,
+        uncompressed: usize,
+        poorly_compressed: bool,
+    ) {
+        self.blocks += 1;
+        self.poorly_compressed += u64::from(poorly_compressed);
+        self.bytes_saved += saved_bytes(compressed, uncompressed);
+        self.compressed_sum += u128::from(MovingAverage::to_fixed(compressed));
+        self.uncompressed_sum += u128::from(MovingAverage::to_fixed(uncompressed));
+        self.ratio_sum += u128::from(fixed_ratio(compressed, uncompressed).unwrap_or(0));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.blocks == 0
+    }
+
+    pub(crate) fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    pub(crate) fn poorly_compressed(&self) -> u64 {
+        self.poorly_compressed
+    }
+
+    pub(crate) fn bytes_saved(&self) -> u64 {
+        self.bytes_saved
+    }
+
+    pub(crate) fn mean_compressed(&self) -> u64 {
+        self.mean(self.compressed_sum)
+    }
+
+    pub(crate) fn mean_uncompressed(&self) -> u64 {
+        self.mean(self.uncompressed_sum)
+    }
+
+    pub(crate) fn mean_ratio(&self) -> u64 {
+        self.mean(self.ratio_sum)
+    }
+
+    /// Returns the mean ratio as a float.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn mean_ratio_f64(&self) -> f64 {
+        self.mean_ratio() as f64 / (1u64 << RATIO_FRACTION_BITS) as f64
+    }
+
+    fn mean(&self, sum: u128) -> u64 {
+        (sum / u128::from(self.blocks.max(1))) as u64
+    }
+}
+
+/// A per-thread handle that batches updates into a shared [`MVCompression`].
+///
+/// Results are validated and classified immediately, but only merged into the
+/// shared state every `flush_every` blocks, on [`flush`](Self::flush) and on
+/// drop. Decisions made through the recorder (or by other threads) therefore
+/// lag by at most `flush_every - 1` of this thread's blocks.
+///
+/// Merging treats the batch as `n` blocks of its mean size and ratio, and moves
+/// the score by the sum of the blocks' weights clamped to the configured bounds,
+/// so the result can differ slightly from applying the blocks one by one.
+///
+/// Each block is checked as it is recorded: observers see
+/// [`SizeOutlier`](crate::EventKind::SizeOutlier) events against the shared
+/// average, which does not yet include pending blocks, and while the
+/// [`PolicyMode`](crate::PolicyMode) does not record outcomes blocks are
+/// forwarded to the shared instance instead of batched.
+///
+/// Window statistics and regime detection need every block in order; with
+/// either configured the recorder forwards each result immediately.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::MVCompression;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let mvc = Arc::new(MVCompression::new());
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let mvc = Arc::clone(&mvc);
+///         thread::spawn(move || {
+///             let mut recorder = mvc.local_recorder(32);
+///             for _ in 0..100 {
+///                 if !recorder.should_skip_compression(64) {
+///                     recorder.update_compression_ratio(16, 64);
+///                 }
+///             }
+///             // Remaining results are merged when the recorder is dropped.
+///         })
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap();
+/// }
+/// assert_eq!(mvc.get_compression_value(), -300);
+/// assert_eq!(mvc.get_uncompressed_average(), 64);
+/// ```
+#[derive(Debug)]
+pub struct LocalRecorder<'a, C: Clock, R: Rng, L: Layout, O: Observer = NoopObserver> {
+    policy: &'a MVCompression<C, R, L, O>,
+    flush_every: u64,
+    batch: OutcomeBatch,
+}
+
+impl<'a, C: Clock, R: Rng, L: Layout, O: Observer> LocalRecorder<'a, C, R, L, O> {
+    /// Creates a recorder merging into `policy` every `flush_every` blocks.
+    ///
+    /// A `flush_every` of 0 or 1 merges every block immediately.
+    pub fn new(policy: &'a MVCompression<C, R, L, O>, flush_every: usize) -> Self {
+        Self {
+            policy,
+            flush_every: (flush_every as u64).max(1),
+            batch: OutcomeBatch::default(),
+        }
+    }
+
+    /// Returns the shared instance this recorder merges into.
+    pub fn policy(&self) -> &'a MVCompression<C, R, L, O> {
+        self.policy
+    }
+
+    /// Decides whether to skip compressing a block, using the shared state.
+    pub fn should_skip_compression(&self, datasize: usize) -> bool {
+        self.policy.should_skip_compression(datasize)
+    }
+
+    /// Records a compression result, ignoring results rejected by
+    /// [`try_update`](Self::try_update).
+    pub fn update_compression_ratio(&mut self, compressed: usize, uncompressed: usize) {
+        let _ = self.try_update(compressed, uncompressed);
+    }
+
+    /// Records a compression result, or reports why it was rejected.
+    ///
+    /// Results are validated exactly like [`MVCompression::try_update`].
+    pub fn try_update(
+        &mut self,
+        compressed: usize,
+        uncompressed: usize,
+    ) -> Result<(), UpdateError> {
+        if !self.policy.supports_batching() || !self.policy.mode().records_outcomes() {
+            return self.policy.try_update(compressed, uncompressed);
+        }
+        self.policy.validate(compressed, uncompressed)?;
+        self.policy.notify_size_outlier(compressed, uncompressed);
+        let poorly_compressed = self
+            .policy
+            .config()
+            .compressible_ratio
+            .is_exceeded_by(compressed, uncompressed);
+        self.batch
+            .record(compressed, uncompressed, poorly_compressed);
+        if self.batch.blocks() >= self.flush_every {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of recorded blocks not yet merged.
+    pub fn pending(&self) -> usize {
+        self.batch.blocks() as usize
+    }
+
+    /// Merges all pending results into the shared instance.
+    pub fn flush(&mut self) {
+        self.policy.merge_outcomes(&self.batch);
+        self.batch = OutcomeBatch::default();
+    }
+}
+
+impl<C: Clock, R: Rng, L: Layout, O: Observer> Drop for LocalRecorder<'_, C, R, L, O> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use crate::mode::PolicyMode;
+    use crate::observer::{Event, EventKind, FnObserver};
+    use crate::regime::RegimeDetectionConfig;
+    use std::sync::Mutex;
+    use std::vec::Vec;
+    use crate::window::{Statistics, WindowStatistic};
+
+    #[test]
+    fn test_results_are_merged_every_n_blocks() {
+        let mvc = MVCompression::new();
+        let mut recorder = mvc.local_recorder(4);
+        for _ in 0..3 {
+            recorder.update_compression_ratio(1000, 1000);
+        }
+        assert_eq!(recorder.pending(), 3);
+        assert_eq!(mvc.get_compression_value(), INITIAL_COMPRESSION_VALUE);
+        assert_eq!(mvc.get_uncompressed_average(), 0);
+
+        recorder.update_compression_ratio(1000, 1000);
+        assert_eq!(recorder.pending(), 0);
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + 4 * NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+        assert_eq!(mvc.get_uncompressed_average(), 1000);
+    }
+
+    #[test]
+    fn test_drop_flushes_pending_results() {
+        let mvc = MVCompression::new();
+        {
+            let mut recorder = mvc.local_recorder(100);
+            recorder.update_compression_ratio(500, 1000);
+            recorder.update_compression_ratio(950, 1000);
+        }
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + COMPRESSIBLE_BLOCK_WEIGHT + NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+        assert_eq!(mvc.get_compressed_average(), 725);
+    }
+
+    #[test]
+    fn test_batches_track_direct_updates() {
+        let direct = MVCompression::new();
+        let batched = MVCompression::new();
+        let mut recorder = batched.local_recorder(8);
+        for i in 0..400 {
+            let compressed = if (i / 50) % 2 == 0 { 300 } else { 1000 };
+            direct.update_compression_ratio(compressed, 1000);
+            recorder.update_compression_ratio(compressed, 1000);
+        }
+        recorder.flush();
+
+        // Direct updates may overshoot a bound by one weight; batches clamp.
+        let score_difference = direct.get_compression_value() - batched.get_compression_value();
+        assert!(score_difference.abs() <= -COMPRESSIBLE_BLOCK_WEIGHT);
+        let average_difference =
+            direct.get_compressed_average_precise() - batched.get_compressed_average_precise();
+        assert!(average_difference.abs() < 50.0);
+        assert!((direct.get_ratio_average() - batched.get_ratio_average()).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_rejected_results_are_not_recorded() {
+        let mvc = MVCompression::new();
+        let mut recorder = mvc.local_recorder(2);
+        assert_eq!(recorder.try_update(10, 0), Err(UpdateError::EmptyBlock));
+        assert!(recorder.try_update(1000, 10).is_err());
+        assert_eq!(recorder.pending(), 0);
+    }
+
+    #[test]
+    fn test_batch_score_respects_bounds() {
+        let mvc = MVCompression::new();
+        let mut recorder = mvc.local_recorder(1000);
+        for _ in 0..500 {
+            recorder.update_compression_ratio(0, 1000);
+        }
+        recorder.flush();
+        assert_eq!(mvc.get_compression_value(), MIN_COMPRESSION_VALUE);
+
+        for _ in 0..500 {
+            recorder.update_compression_ratio(1000, 1000);
+        }
+        recorder.flush();
+        assert_eq!(mvc.get_compression_value(), MAX_COMPRESSION_VALUE);
+    }
+
+    #[test]
+    fn test_order_sensitive_features_forward_immediately() {
+        let window = MVCompression::with_config(MVCompressionConfig {
+            statistics: Statistics::window(WindowStatistic::Median),
+            ..MVCompressionConfig::default()
+        });
+        let mut recorder = window.local_recorder(16);
+        recorder.update_compression_ratio(1000, 1000);
+        assert_eq!(recorder.pending(), 0);
+        assert_eq!(window.window_stats().unwrap().samples, 1);
+
+        let regime = MVCompression::with_config(MVCompressionConfig {
+            regime_detection: Some(RegimeDetectionConfig::default()),
+            ..MVCompressionConfig::default()
+        });
+        let mut recorder = regime.local_recorder(16);
+        recorder.update_compression_ratio(1000, 1000);
+        assert_eq!(recorder.pending(), 0);
+        assert_eq!(
+            regime.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+
+    #[test]
+    fn test_batched_blocks_raise_size_outliers() {
+        let kinds = Mutex::new(Vec::new());
+        let observer = FnObserver(|event: &Event| kinds.lock().unwrap().push(event.kind));
+        let mvc = MVCompression::builder().observer(&observer).build();
+        let mut recorder = mvc.local_recorder(16);
+        recorder.update_compression_ratio(500, 1000);
+        recorder.flush();
+        recorder.update_compression_ratio(2000, 4000);
+        recorder.update_compression_ratio(3000, 6000);
+        recorder.update_compression_ratio(10, 100);
+        assert_eq!(recorder.pending(), 3);
+        assert_eq!(
+            *kinds.lock().unwrap(),
+            [EventKind::SizeOutlier, EventKind::SizeOutlier]
+        );
+    }
+
+    #[test]
+    fn test_mode_is_checked_per_block() {
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            counters: true,
+            ..MVCompressionConfig::default()
+        });
+        let mut recorder = mvc.local_recorder(16);
+        recorder.update_compression_ratio(1000, 1000);
+        mvc.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: false,
+        });
+        // Not recorded by the mode, but still counted.
+        recorder.update_compression_ratio(1000, 1000);
+        assert_eq!(recorder.pending(), 1);
+        assert_eq!(mvc.stats().outcomes, 1);
+
+        // The block recorded before the switch still applies.
+        recorder.flush();
+        assert_eq!(mvc.stats().outcomes, 2);
+        assert_eq!(
+            mvc.get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+}
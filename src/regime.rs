@@ -0,0 +1,209 @@
+//! Regime-change detection over observed compression ratios.
+//!
+//! The bounded score moves a few points per block, so when a stream switches
+//! from, say, text to encrypted data it can take dozens of blocks for the score
+//! to cross zero. The detector runs a two-sided CUSUM over the difference
+//! between each block's ratio and the ratio predicted by the moving averages.
+//! When the cumulative deviation in either direction exceeds a threshold the
+//! distribution has shifted, and [`MVCompression`](crate::MVCompression) jumps
+//! its learned state to match the new regime instead of walking there.
+
+use crate::atomic::{AtomicU64, Ordering};
+
+use crate::ratio::ratio_from_f32;
+
+/// Default per-block allowance before deviations start to accumulate.
+const DEFAULT_SLACK: f32 = 0.05;
+
+/// Default cumulative deviation that signals a regime change.
+const DEFAULT_THRESHOLD: f32 = 0.5;
+
+/// Direction of a detected shift in the ratio distribution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegimeShift {
+    /// Ratios rose: data became less compressible (e.g. text to encrypted).
+    LessCompressible,
+    /// Ratios fell: data became more compressible.
+    MoreCompressible,
+}
+
+/// A detected regime change, passed to [`RegimeDetectionConfig::on_change`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegimeChange {
+    /// Direction of the shift.
+    pub shift: RegimeShift,
+    /// Ratio of the block that triggered detection.
+    pub ratio: f32,
+    /// Ratio predicted by the moving averages before the block.
+    pub reference_ratio: f32,
+    /// Compression value before the jump.
+    pub previous_value: i32,
+    /// Compression value after the jump.
+    pub new_value: i32,
+}
+
+/// Configuration of regime-change detection.
+///
+/// On a [`RegimeShift::LessCompressible`] shift the compression value jumps to
+/// at least zero, so the next poor block enables skipping. On a
+/// [`RegimeShift::MoreCompressible`] shift it drops to at most the initial
+/// value, so compression resumes immediately. In both cases the moving averages
+/// are re-seeded from the triggering block.
+#[derive(Debug, Clone, Copy)]
+pub struct RegimeDetectionConfig {
+    /// Deviation from the predicted ratio tolerated per block before it counts
+    /// toward detection.
+    pub slack: f32,
+    /// Cumulative deviation, in ratio units, that signals a regime change.
+    pub threshold: f32,
+    /// Called once for every detected regime change, after the instance's
+    /// [`Observer`](crate::Observer) has received an
+    /// [`EventKind::RegimeChange`](crate::EventKind::RegimeChange) event. Kept
+    /// for callers that only need the direction of the shift; observers see
+    /// the same changes.
+    pub on_change: Option<fn(RegimeChange)>,
+}
+
+impl RegimeDetectionConfig {
+    /// Returns the default detection parameters.
+    pub const fn new() -> Self {
+        Self {
+            slack: DEFAULT_SLACK,
+            threshold: DEFAULT_THRESHOLD,
+            on_change: None,
+        }
+    }
+}
+
+impl Default for RegimeDetectionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lock-free two-sided CUSUM state.
+///
+/// Both cumulative sums are packed into a single atomic word (upper half:
+/// upward deviations, lower half: downward deviations) so each observation
+/// updates them together with one compare-exchange.
+#[derive(Debug)]
+pub(crate) struct RegimeDetector {
+    sums: AtomicU64,
+}
+
+impl RegimeDetector {
+    pub(crate) const fn new() -> Self {
+        Self {
+            sums: AtomicU64::new(0),
+        }
+    }
+
+    /// Discards the accumulated deviations.
+    pub(crate) fn reset(&self) {
+        self.sums.store(0, Ordering::Relaxed);
+    }
+
+    /// Feeds one observation into the detector.
+    ///
+    /// `ratio` and `reference` are fixed-point ratios (see `crate::ratio`).
+    ///
+    /// Returns the detected shift, if any. Detection resets both sums, and only
+    /// the thread whose compare-exchange performed the reset sees the shift.
+    pub(crate) fn observe(
+        &self,
+        ratio: u64,
+        reference: u64,
+        config: &RegimeDetectionConfig,
+    ) -> Option<RegimeShift> {
+        let slack = ratio_from_f32(config.slack) as i64;
+        let threshold = ratio_from_f32(config.threshold);
+        let deviation = ratio as i64 - reference as i64;
+
+        loop {
+            let current = self.sums.load(Ordering::Relaxed);
+            let (up, down) = unpack(current);
+            // Standard CUSUM recursion: S = max(0, S + deviation - slack).
+            let up = (up as i64 + deviation - slack).max(0) as u64;
+            let down = (down as i64 - deviation - slack).max(0) as u64;
+
+            let shift = if up > threshold {
+                Some(RegimeShift::LessCompressible)
+            } else if down > threshold {
+                Some(RegimeShift::MoreCompressible)
+            } else {
+                None
+            };
+            let new_sums = if shift.is_some() { 0 } else { pack(up, down) };
+
+            match self.sums.compare_exchange_weak(
+                current,
+                new_sums,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return shift,
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+fn pack(up: u64, down: u64) -> u64 {
+    (up.min(u32::MAX as u64) << 32) | down.min(u32::MAX as u64)
+}
+
+fn unpack(sums: u64) -> (u64, u64) {
+    (sums >> 32, sums & u32::MAX as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratio::fixed_ratio;
+
+    #[test]
+    fn test_stable_ratios_never_trigger() {
+        let detector = RegimeDetector::new();
+        let config = RegimeDetectionConfig::default();
+        let reference = fixed_ratio(30, 100).unwrap();
+        for i in 0..1000 {
+            // Jitter within the slack.
+            let ratio = fixed_ratio(28 + (i % 5), 100).unwrap();
+            assert_eq!(detector.observe(ratio, reference, &config), None);
+        }
+    }
+
+    #[test]
+    fn test_sustained_rise_and_fall_are_detected() {
+        let detector = RegimeDetector::new();
+        let config = RegimeDetectionConfig::default();
+        let low = fixed_ratio(30, 100).unwrap();
+        let high = fixed_ratio(100, 100).unwrap();
+
+        assert_eq!(
+            detector.observe(high, low, &config),
+            Some(RegimeShift::LessCompressible)
+        );
+        assert_eq!(
+            detector.observe(low, high, &config),
+            Some(RegimeShift::MoreCompressible)
+        );
+    }
+
+    #[test]
+    fn test_small_deviations_accumulate() {
+        let detector = RegimeDetector::new();
+        let config = RegimeDetectionConfig::default();
+        let reference = fixed_ratio(50, 100).unwrap();
+        let ratio = fixed_ratio(64, 100).unwrap();
+
+        // 0.09 above the slack per block crosses 0.5 on the sixth block.
+        for _ in 0..5 {
+            assert_eq!(detector.observe(ratio, reference, &config), None);
+        }
+        assert_eq!(
+            detector.observe(ratio, reference, &config),
+            Some(RegimeShift::LessCompressible)
+        );
+    }
+}
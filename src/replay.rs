@@ -0,0 +1,385 @@
+//! Offline evaluation of configurations by replaying recorded blocks.
+//!
+//! A [`Simulator`] feeds a sequence of [`ReplayBlock`]s, each with its known
+//! compressed size, through a fresh instance with a given configuration. Blocks
+//! the instance decides to compress report their recorded result; skipped
+//! blocks report nothing, as in production. The [`ReplayReport`] compares the
+//! outcome with an oracle that knew every block's ratio and compressed exactly
+//! the blocks whose ratio does not exceed the oracle threshold.
+//!
+//! Replays are deterministic: time comes from the blocks' timestamps through a
+//! [`ManualClock`] and probes from a [`SeededRng`]. Blocks can be captured with
+//! a `TraceRecorder` on an instance in
+//! [`PolicyMode::AlwaysCompress`](crate::PolicyMode::AlwaysCompress), so that
+//! every block has a known compressed size, and read back with
+//! `blocks_from_trace`.
+
+use crate::clock::ManualClock;
+use crate::config::{MVCompressionConfig, BLOCK_COMPRESSABLE_RATIO};
+use crate::mvcompression::MVCompression;
+use crate::observer::TraceEntry;
+use crate::ratio::Ratio;
+use crate::rng::SeededRng;
+
+/// One recorded block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayBlock {
+    /// Clock reading when the block was seen; `0` if unknown.
+    pub timestamp_nanos: u64,
+    /// Uncompressed size, in bytes. Blocks of size zero are ignored.
+    pub uncompressed: usize,
+    /// Size the block compressed to, in bytes.
+    pub compressed: usize,
+}
+
+impl ReplayBlock {
+    /// Returns a block without a timestamp.
+    pub const fn new(uncompressed: usize, compressed: usize) -> Self {
+        Self {
+            timestamp_nanos: 0,
+            uncompressed,
+            compressed,
+        }
+    }
+
+    /// Returns the bytes compressing the block saves; expansions save nothing.
+    pub fn savings(&self) -> u64 {
+        self.uncompressed.saturating_sub(self.compressed) as u64
+    }
+}
+
+/// Replays blocks through instances with arbitrary configurations.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::replay::{ReplayBlock, Simulator};
+/// use mvcompression::MVCompressionConfig;
+///
+/// // Text-like blocks followed by a long run of incompressible ones.
+/// let blocks: Vec<_> = (0..1000)
+///     .map(|i| if i < 200 { ReplayBlock::new(4096, 1024) } else { ReplayBlock::new(4096, 4090) })
+///     .collect();
+///
+/// let report = Simulator::new().run(MVCompressionConfig::default(), blocks.iter().copied());
+/// assert_eq!(report.blocks, 1000);
+/// assert_eq!(report.oracle_bytes_saved, 200 * 3072);
+/// assert!(report.skip_rate() > 0.5);
+/// assert_eq!(report.missed, 0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Simulator {
+    oracle: Ratio,
+    seed: u64,
+}
+
+impl Simulator {
+    /// Returns a simulator whose oracle compresses blocks with a ratio of at
+    /// most the default compressible ratio (0.9), seeding probes with zero.
+    pub const fn new() -> Self {
+        Self {
+            oracle: BLOCK_COMPRESSABLE_RATIO,
+            seed: 0,
+        }
+    }
+
+    /// Sets the largest ratio at which the oracle still compresses a block.
+    pub const fn oracle(self, threshold: Ratio) -> Self {
+        Self {
+            oracle: threshold,
+            ..self
+        }
+    }
+
+    /// Sets the seed of the random source used for probes.
+    pub const fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Replays `blocks` through a fresh instance with `config`.
+    pub fn run<I>(&self, config: MVCompressionConfig, blocks: I) -> ReplayReport
+    where
+        I: IntoIterator<Item = ReplayBlock>,
+    {
+        let clock = ManualClock::new();
+        let policy = MVCompression::builder()
+            .config(config)
+            .clock(&clock)
+            .rng(SeededRng::new(self.seed))
+            .build();
+        let mut report = ReplayReport::default();
+        let mut now = 0;
+        for block in blocks {
+            if block.uncompressed == 0 {
+                continue;
+            }
+            now = now.max(block.timestamp_nanos);
+            clock.set_nanos(now);
+
+            let worth_compressing = !self
+                .oracle
+                .is_exceeded_by(block.compressed, block.uncompressed);
+            let savings = block.savings();
+            report.blocks += 1;
+            report.uncompressed_bytes += block.uncompressed as u64;
+            if worth_compressing {
+                report.oracle_attempts += 1;
+                report.oracle_bytes_saved += savings;
+            }
+
+            if policy.should_skip_compression(block.uncompressed) {
+                report.skipped += 1;
+                if worth_compressing {
+                    report.missed += 1;
+                    report.bytes_forgone += savings;
+                }
+            } else if policy.try_update(block.compressed, block.uncompressed).is_err() {
+                report.rejected += 1;
+            } else {
+                report.attempts += 1;
+                report.attempted_bytes += block.uncompressed as u64;
+                report.bytes_saved += savings;
+                if !worth_compressing {
+                    report.wasted_attempts += 1;
+                    report.wasted_bytes += block.uncompressed as u64;
+                }
+            }
+        }
+        report.final_score = policy.get_compression_value();
+        report
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a replay did, compared with the oracle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReplayReport {
+    /// Number of blocks replayed.
+    pub blocks: u64,
+    /// Sum of the blocks' uncompressed sizes.
+    pub uncompressed_bytes: u64,
+    /// Blocks the policy skipped.
+    pub skipped: u64,
+    /// Blocks the policy compressed and whose result it accepted.
+    pub attempts: u64,
+    /// Blocks the policy compressed but whose result it rejected as
+    /// impossible (see [`MVCompression::try_update`]). They count toward
+    /// neither attempts nor savings.
+    pub rejected: u64,
+    /// Uncompressed bytes of the blocks the policy compressed, i.e. the
+    /// compressor's input.
+    pub attempted_bytes: u64,
+    /// Compressed blocks the oracle would have skipped.
+    pub wasted_attempts: u64,
+    /// Uncompressed bytes of the wasted attempts, i.e. compressor input spent
+    /// for nothing.
+    pub wasted_bytes: u64,
+    /// Skipped blocks the oracle would have compressed.
+    pub missed: u64,
+    /// Bytes saved by the blocks the policy compressed.
+    pub bytes_saved: u64,
+    /// Bytes the missed blocks would have saved.
+    pub bytes_forgone: u64,
+    /// Blocks the oracle compressed.
+    pub oracle_attempts: u64,
+    /// Bytes the oracle saved.
+    pub oracle_bytes_saved: u64,
+    /// Score of the instance after the last block.
+    pub final_score: i32,
+}
+
+impl ReplayReport {
+    /// Returns the fraction of blocks skipped.
+    pub fn skip_rate(&self) -> f64 {
+        ratio_of(self.skipped, self.blocks)
+    }
+
+    /// Returns the fraction of the oracle's savings the policy achieved.
+    ///
+    /// Can exceed `1.0`, since the policy also keeps the small savings of
+    /// blocks the oracle considers not worth compressing.
+    pub fn savings_efficiency(&self) -> f64 {
+        ratio_of(self.bytes_saved, self.oracle_bytes_saved)
+    }
+
+    /// Returns the fraction of compression attempts the oracle would have
+    /// skipped.
+    pub fn wasted_attempt_rate(&self) -> f64 {
+        ratio_of(self.wasted_attempts, self.attempts)
+    }
+}
+
+/// Returns `part / whole`, or `0.0` for an empty whole.
+fn ratio_of(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// Returns the block of a traced outcome; other entries have no known
+/// compressed size.
+pub fn block_from_entry(entry: &TraceEntry) -> Option<ReplayBlock> {
+    match *entry {
+        TraceEntry::Outcome {
+            timestamp_nanos,
+            compressed,
+            uncompressed,
+            ..
+        } => Some(ReplayBlock {
+            timestamp_nanos,
+            uncompressed,
+            compressed,
+        }),
+        TraceEntry::Decision { .. } | TraceEntry::Batch { .. } => None,
+    }
+}
+
+/// Reads the outcomes of a trace as blocks to replay.
+///
+/// Decisions and merged batches are skipped. Decoding errors are passed on.
+#[cfg(feature = "std")]
+pub fn blocks_from_trace<R: std::io::Read>(
+    reader: crate::tracelog::TraceReader<R>,
+) -> impl Iterator<Item = std::io::Result<ReplayBlock>> {
+    reader.filter_map(|entry| match entry {
+        Ok(entry) => block_from_entry(&entry).map(Ok),
+        Err(error) => Some(Err(error)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{COMPRESSIBLE_BLOCK_WEIGHT, INITIAL_COMPRESSION_VALUE};
+
+    /// Alternating runs of compressible and incompressible blocks.
+    fn bimodal(runs: usize, run_length: usize) -> impl Iterator<Item = ReplayBlock> + Clone {
+        (0..runs * run_length).map(move |i| {
+            if (i / run_length).is_multiple_of(2) {
+                ReplayBlock::new(1000, 300)
+            } else {
+                ReplayBlock::new(1000, 1000)
+            }
+        })
+    }
+
+    #[test]
+    fn test_report_accounts_for_every_block() {
+        let report = Simulator::new().run(MVCompressionConfig::default(), bimodal(10, 100));
+        assert_eq!(report.blocks, 1000);
+        assert_eq!(report.skipped + report.attempts, report.blocks);
+        assert_eq!(report.rejected, 0);
+        assert_eq!(report.oracle_attempts, 500);
+        assert_eq!(report.oracle_bytes_saved, 500 * 700);
+        assert_eq!(
+            report.bytes_saved + report.bytes_forgone,
+            report.oracle_bytes_saved
+        );
+        assert!(report.skipped > 0);
+        assert!(report.wasted_attempt_rate() > 0.0 && report.wasted_attempt_rate() < 1.0);
+    }
+
+    #[test]
+    fn test_replays_are_deterministic_and_compare_configs() {
+        let probing = MVCompressionConfig {
+            probe_rate: 4,
+            ..MVCompressionConfig::default()
+        };
+        let first = Simulator::new().seed(7).run(probing, bimodal(6, 200));
+        let second = Simulator::new().seed(7).run(probing, bimodal(6, 200));
+        assert_eq!(first, second);
+
+        // A lighter penalty for incompressible blocks delays skipping.
+        let sticky = MVCompressionConfig {
+            non_compressible_block_weight: 1,
+            ..MVCompressionConfig::default()
+        };
+        let baseline = Simulator::new().run(MVCompressionConfig::default(), bimodal(6, 200));
+        let delayed = Simulator::new().run(sticky, bimodal(6, 200));
+        assert!(delayed.skipped < baseline.skipped);
+    }
+
+    #[test]
+    fn test_oracle_threshold_and_empty_blocks() {
+        let blocks = [
+            ReplayBlock::new(1000, 500),
+            ReplayBlock::new(0, 0),
+            ReplayBlock::new(1000, 950),
+        ];
+        let strict = Simulator::new()
+            .oracle(Ratio::new(1, 2))
+            .run(MVCompressionConfig::default(), blocks);
+        assert_eq!(strict.blocks, 2);
+        assert_eq!(strict.oracle_attempts, 1);
+        assert_eq!(strict.wasted_attempts, 1);
+        assert_eq!(strict.wasted_bytes, 1000);
+        assert_eq!(strict.attempted_bytes, 2000);
+        assert_eq!(strict.bytes_saved, 550);
+        assert!(strict.savings_efficiency() > 1.0);
+        assert_eq!(ReplayReport::default().skip_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_rejected_results_are_counted_separately() {
+        let blocks = [
+            ReplayBlock::new(1000, 500),
+            ReplayBlock::new(100, 300),
+            ReplayBlock::new(1000, 400),
+        ];
+        let report = Simulator::new().run(MVCompressionConfig::default(), blocks);
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.rejected, 1);
+        assert_eq!(report.attempts, 2);
+        assert_eq!(report.attempted_bytes, 2000);
+        assert_eq!(report.bytes_saved, 1100);
+        assert_eq!(report.wasted_attempts, 0);
+        assert_eq!(
+            report.final_score,
+            INITIAL_COMPRESSION_VALUE + 2 * COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_blocks_are_read_from_traces() {
+        use crate::mode::PolicyMode;
+        use crate::tracelog::{TraceReader, TraceRecorder};
+        use std::vec::Vec;
+
+        let recorder = TraceRecorder::ring(1024);
+        let production = MVCompression::builder().observer(&recorder).build();
+        production.set_mode(PolicyMode::AlwaysCompress {
+            record_outcomes: true,
+        });
+        for block in bimodal(2, 50) {
+            if !production.should_skip_compression(block.uncompressed) {
+                production.update_compression_ratio(block.compressed, block.uncompressed);
+            }
+        }
+        let mut trace = Vec::new();
+        recorder.write_to(&mut trace).unwrap();
+
+        let blocks: Vec<_> = blocks_from_trace(TraceReader::new(&trace[..]).unwrap())
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(blocks.len(), 100);
+        assert!(blocks.iter().zip(bimodal(2, 50)).all(|(traced, block)| (
+            traced.uncompressed,
+            traced.compressed
+        ) == (
+            block.uncompressed,
+            block.compressed
+        )));
+        assert!(blocks
+            .windows(2)
+            .all(|pair| pair[0].timestamp_nanos <= pair[1].timestamp_nanos));
+        let report = Simulator::new().run(MVCompressionConfig::default(), blocks);
+        assert_eq!(report.blocks, 100);
+    }
+}
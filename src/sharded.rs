@@ -0,0 +1,671 @@
+//! Sharded decision state with periodic consolidation.
+//!
+//! A single [`MVCompression`] is shared by every thread, so all of them write
+//! the same atomics. [`ShardedMVCompression`] instead keeps one independent
+//! instance per shard. Each thread decides and learns on its own shard without
+//! touching the others, and every so often the shards are reconciled: their
+//! score changes are summed and their averages are merged weighted by the number
+//! of blocks each shard saw, and the result is written back to every shard.
+
+use std::thread;
+use std::vec::Vec;
+
+use crate::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use crate::clock::{Clock, DefaultClock};
+use crate::config::MVCompressionConfig;
+use crate::error::UpdateError;
+use crate::histogram::Histograms;
+use crate::layout::{CachePadded, Layout, Slot};
+use crate::mode::{PolicyMode, SkipState};
+use crate::mvcompression::MVCompression;
+use crate::observer::{NoopObserver, Observer};
+use crate::rng::{Rng, SystemRng};
+use crate::snapshot::Snapshot;
+use crate::stats::PolicyStats;
+
+/// Default number of a shard's blocks between consolidations.
+const DEFAULT_CONSOLIDATION_INTERVAL: u64 = 1024;
+
+/// Shape of a [`ShardedMVCompression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardingConfig {
+    /// Number of independent shards, at least one.
+    pub shards: usize,
+    /// Number of blocks a shard records before it triggers a consolidation.
+    /// `0` disables automatic consolidation; call
+    /// [`ShardedMVCompression::consolidate`] instead.
+    pub consolidation_interval: u64,
+}
+
+impl ShardingConfig {
+    /// Returns one shard per available hardware thread and the default interval.
+    pub fn new() -> Self {
+        Self {
+            shards: thread::available_parallelism().map_or(1, |n| n.get()),
+            consolidation_interval: DEFAULT_CONSOLIDATION_INTERVAL,
+        }
+    }
+
+    /// Returns `shards` shards with the default interval.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards,
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for ShardingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One shard: an independent instance and its blocks since the last consolidation.
+#[derive(Debug)]
+struct Shard<C, R, L: Layout, O> {
+    policy: MVCompression<C, R, L, O>,
+    blocks: Slot<AtomicU64, L>,
+}
+
+/// A set of independent [`MVCompression`] shards that are periodically reconciled.
+///
+/// Threads are assigned to shards round-robin the first time they use any
+/// sharded instance; the `_on` methods address a shard explicitly, e.g. the
+/// current CPU. Decisions only read the caller's shard, so between
+/// consolidations a shard does not see what the others learned.
+///
+/// A consolidation adds up the score changes of all shards since the previous
+/// consolidation (clamped to the configured bounds), merges the averages
+/// weighted by each shard's block count, and writes the result back to every
+/// shard. Score changes made while a consolidation is in progress are kept on
+/// top of the consolidated score, but blocks recorded meanwhile may be lost from
+/// the merged averages. Window statistics and regime detection stay per shard.
+///
+/// Shards default to the [`CachePadded`] layout, since avoiding shared cache
+/// lines is the point of sharding.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{ShardedMVCompression, ShardingConfig};
+///
+/// let sharded = ShardedMVCompression::with_sharding(ShardingConfig {
+///     shards: 4,
+///     consolidation_interval: 0,
+/// });
+/// sharded.update_compression_ratio_on(0, 500, 1000);
+/// sharded.update_compression_ratio_on(1, 500, 1000);
+///
+/// // The global view already includes both shards' progress.
+/// assert_eq!(sharded.snapshot().compression_value, -100);
+/// assert_eq!(sharded.shard(2).get_compression_value(), -80);
+///
+/// sharded.consolidate();
+/// assert_eq!(sharded.shard(2).get_compression_value(), -100);
+/// ```
+#[derive(Debug)]
+pub struct ShardedMVCompression<
+    C = DefaultClock,
+    R = SystemRng,
+    L: Layout = CachePadded,
+    O = NoopObserver,
+> {
+    shards: Box<[Shard<C, R, L, O>]>,
+    sharding: ShardingConfig,
+    /// Score every shard was set to by the last consolidation.
+    consolidated_value: AtomicI32,
+    /// Held by the thread currently consolidating.
+    consolidating: AtomicBool,
+    /// Number of completed consolidations.
+    consolidations: AtomicU64,
+}
+
+impl ShardedMVCompression {
+    /// Creates a sharded instance with the default configuration and sharding.
+    pub fn new() -> Self {
+        Self::with_sharding(ShardingConfig::new())
+    }
+
+    /// Creates a sharded instance with the default configuration.
+    pub fn with_sharding(sharding: ShardingConfig) -> Self {
+        MVCompression::builder()
+            .layout::<CachePadded>()
+            .build_sharded(sharding)
+    }
+}
+
+impl Default for ShardedMVCompression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock, R: Rng, L: Layout, O: Observer> ShardedMVCompression<C, R, L, O> {
+    pub(crate) fn from_parts(
+        config: MVCompressionConfig,
+        sharding: ShardingConfig,
+        clock: C,
+        rng: R,
+        observer: O,
+    ) -> Self
+    where
+        C: Clone,
+        R: Clone,
+        O: Clone,
+    {
+        let shards = (0..sharding.shards.max(1))
+            .map(|_| Shard {
+                policy: MVCompression::from_parts(
+                    config,
+                    clock.clone(),
+                    rng.clone(),
+                    observer.clone(),
+                ),
+                blocks: Slot::new(AtomicU64::new(0)),
+            })
+            .collect();
+        Self {
+            shards,
+            sharding,
+            consolidated_value: AtomicI32::new(config.initial_compression_value),
+            consolidating: AtomicBool::new(false),
+            consolidations: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the configuration shared by all shards.
+    pub fn config(&self) -> &MVCompressionConfig {
+        self.shards[0].policy.config()
+    }
+
+    /// Returns the sharding configuration.
+    pub fn sharding(&self) -> &ShardingConfig {
+        &self.sharding
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the instance backing shard `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is not below [`shard_count`](Self::shard_count).
+    pub fn shard(&self, index: usize) -> &MVCompression<C, R, L, O> {
+        &self.shards[index].policy
+    }
+
+    /// Returns the shard used by the calling thread.
+    pub fn current_shard(&self) -> usize {
+        thread_hint() % self.shards.len()
+    }
+
+    /// Decides whether to skip compressing a block, using the calling thread's shard.
+    pub fn should_skip_compression(&self, datasize: usize) -> bool {
+        self.should_skip_compression_on(self.current_shard(), datasize)
+    }
+
+    /// Decides whether to skip compressing a block, using shard `index` modulo
+    /// the shard count.
+    pub fn should_skip_compression_on(&self, index: usize, datasize: usize) -> bool {
+        self.shards[index % self.shards.len()]
+            .policy
+            .should_skip_compression(datasize)
+    }
+
+    /// Records a compression result on the calling thread's shard.
+    pub fn update_compression_ratio(&self, compressed: usize, uncompressed: usize) {
+        let _ = self.try_update(compressed, uncompressed);
+    }
+
+    /// Records a compression result on shard `index` modulo the shard count.
+    pub fn update_compression_ratio_on(
+        &self,
+        index: usize,
+        compressed: usize,
+        uncompressed: usize,
+    ) {
+        let _ = self.try_update_on(index, compressed, uncompressed);
+    }
+
+    /// Records a compression result on the calling thread's shard, or reports
+    /// why it was rejected (see [`MVCompression::try_update`]).
+    pub fn try_update(&self, compressed: usize, uncompressed: usize) -> Result<(), UpdateError> {
+        self.try_update_on(self.current_shard(), compressed, uncompressed)
+    }
+
+    /// Records a compression result on shard `index` modulo the shard count, or
+    /// reports why it was rejected.
+    ///
+    /// Triggers a consolidation once the shard has recorded
+    /// [`ShardingConfig::consolidation_interval`] blocks since the last one.
+    pub fn try_update_on(
+        &self,
+        index: usize,
+        compressed: usize,
+        uncompressed: usize,
+    ) -> Result<(), UpdateError> {
+        let shard = &self.shards[index % self.shards.len()];
+        shard.policy.try_update(compressed, uncompressed)?;
+        let blocks = shard.blocks.fetch_add(1, Ordering::Relaxed) + 1;
+        let interval = self.sharding.consolidation_interval;
+        if interval > 0 && blocks >= interval {
+            self.consolidate();
+        }
+        Ok(())
+    }
+
+    /// Reconciles all shards now.
+    ///
+    /// Returns false without doing anything if another thread is already
+    /// consolidating.
+    pub fn consolidate(&self) -> bool {
+        if self
+            .consolidating
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        let mut seen = Vec::with_capacity(self.shards.len());
+        let merged = self.merge(true, |value| seen.push(value));
+        for (shard, seen) in self.shards.iter().zip(seen) {
+            shard.policy.rebase(&merged, seen);
+        }
+        self.consolidated_value
+            .store(merged.compression_value, Ordering::Relaxed);
+        self.consolidations.fetch_add(1, Ordering::Relaxed);
+        self.consolidating.store(false, Ordering::Release);
+        true
+    }
+
+    /// Resets every shard to the initial state (see [`MVCompression::reset`]).
+    pub fn reset(&self) {
+        self.overwrite(|policy| {
+            policy.reset();
+            policy.get_compression_value()
+        });
+    }
+
+    /// Resets every shard to `snapshot` (see [`MVCompression::reset_to`]).
+    pub fn reset_to(&self, snapshot: &Snapshot) {
+        self.overwrite(|policy| {
+            policy.reset_to(snapshot);
+            policy.get_compression_value()
+        });
+    }
+
+    /// Forces the compression value of every shard (see
+    /// [`MVCompression::force_value`]) and returns the value stored.
+    pub fn force_value(&self, value: i32) -> i32 {
+        self.overwrite(|policy| policy.force_value(value))
+    }
+
+    /// Returns the operator override (see [`MVCompression::mode`]).
+    pub fn mode(&self) -> PolicyMode {
+        self.shards[0].policy.mode()
+    }
+
+    /// Switches the operator override of every shard and returns the previous
+    /// mode (see [`MVCompression::set_mode`]).
+    pub fn set_mode(&self, mode: PolicyMode) -> PolicyMode {
+        let previous = self.mode();
+        for shard in self.shards.iter() {
+            shard.policy.set_mode(mode);
+        }
+        previous
+    }
+
+    /// Returns the statistics of all shards added up.
+    ///
+    /// The skip state is [`SkipState::Skipping`] while any shard skips.
+    pub fn stats(&self) -> PolicyStats {
+        self.shards
+            .iter()
+            .map(|shard| shard.policy.stats())
+            .fold(PolicyStats::default(), |total, stats| PolicyStats {
+                skip_state: if stats.skip_state == SkipState::Skipping {
+                    SkipState::Skipping
+                } else {
+                    total.skip_state
+                },
+                skip_entered: total.skip_entered + stats.skip_entered,
+                skip_exited: total.skip_exited + stats.skip_exited,
+                skipped: total.skipped + stats.skipped,
+                attempted: total.attempted + stats.attempted,
+                outcomes: total.outcomes + stats.outcomes,
+                bytes_saved: total.bytes_saved + stats.bytes_saved,
+            })
+    }
+
+    /// Returns the histograms of all shards added up (see
+    /// [`MVCompression::histograms`]).
+    pub fn histograms(&self) -> Option<Histograms> {
+        let mut shards = self.shards.iter().map(|shard| shard.policy.histograms());
+        let mut total = shards.next()??;
+        for histograms in shards.flatten() {
+            total.merge(&histograms);
+        }
+        Some(total)
+    }
+
+    /// Applies `f` to every shard while no consolidation runs, and makes the
+    /// resulting score the new consolidation baseline.
+    fn overwrite(&self, f: impl Fn(&MVCompression<C, R, L, O>) -> i32) -> i32 {
+        while self
+            .consolidating
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        let mut value = 0;
+        for shard in self.shards.iter() {
+            value = f(&shard.policy);
+            shard.blocks.store(0, Ordering::Relaxed);
+        }
+        self.consolidated_value.store(value, Ordering::Relaxed);
+        self.consolidating.store(false, Ordering::Release);
+        value
+    }
+
+    /// Returns the number of completed consolidations.
+    pub fn consolidations(&self) -> u64 {
+        self.consolidations.load(Ordering::Relaxed)
+    }
+
+    /// Returns the global state: what a consolidation would produce right now.
+    ///
+    /// The shards are not modified. `regime_changes` is the total over all shards.
+    pub fn snapshot(&self) -> Snapshot {
+        self.merge(false, |_| {})
+    }
+
+    /// Merges the shards' states; `take` resets their block counts. `seen`
+    /// receives the score of every shard the merge was computed from.
+    fn merge(&self, take: bool, mut seen: impl FnMut(i32)) -> Snapshot {
+        let config = self.config();
+        let base = self.consolidated_value.load(Ordering::Relaxed);
+        let mut delta = 0i64;
+        let mut weight = 0u64;
+        let mut compressed = 0.0;
+        let mut uncompressed = 0.0;
+        let mut ratio = 0.0;
+        let mut regime_changes = 0;
+        let mut fallback = None;
+
+        for shard in self.shards.iter() {
+            let snapshot = shard.policy.snapshot();
+            let blocks = if take {
+                shard.blocks.swap(0, Ordering::Relaxed)
+            } else {
+                shard.blocks.load(Ordering::Relaxed)
+            };
+            seen(snapshot.compression_value);
+            delta += i64::from(snapshot.compression_value) - i64::from(base);
+            regime_changes += snapshot.regime_changes;
+            if snapshot.has_samples() {
+                fallback.get_or_insert(snapshot);
+                if blocks > 0 {
+                    let w = blocks as f64;
+                    weight += blocks;
+                    compressed += w * snapshot.compressed_average;
+                    uncompressed += w * snapshot.uncompressed_average;
+                    ratio += w * snapshot.ratio_average;
+                }
+            }
+        }
+
+        // Never push a value that already lies outside the bounds further out.
+        let low = config.min_compression_value.min(base);
+        let high = config.max_compression_value.max(base);
+        let compression_value =
+            (i64::from(base) + delta).clamp(i64::from(low), i64::from(high)) as i32;
+
+        let (compressed_average, uncompressed_average, ratio_average) = if weight > 0 {
+            let w = weight as f64;
+            (compressed / w, uncompressed / w, ratio / w)
+        } else {
+            // Without new blocks all shards still hold the consolidated averages.
+            fallback.map_or((0.0, 0.0, 0.0), |s| {
+                (
+                    s.compressed_average,
+                    s.uncompressed_average,
+                    s.ratio_average,
+                )
+            })
+        };
+
+        Snapshot {
+            compression_value,
+            compressed_average,
+            uncompressed_average,
+            ratio_average,
+            regime_changes,
+        }
+    }
+}
+
+/// Returns a per-thread value assigned round-robin on first use.
+fn thread_hint() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static HINT: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    HINT.with(|hint| *hint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::*;
+    use std::sync::Arc;
+
+    fn manual(shards: usize) -> ShardedMVCompression {
+        ShardedMVCompression::with_sharding(ShardingConfig {
+            shards,
+            consolidation_interval: 0,
+        })
+    }
+
+    #[test]
+    fn test_shards_learn_independently_until_consolidated() {
+        let sharded = manual(4);
+        for _ in 0..3 {
+            sharded.update_compression_ratio_on(0, 0, 1000);
+        }
+        sharded.update_compression_ratio_on(1, 0, 2000);
+
+        assert_eq!(sharded.shard(0).get_compression_value(), -110);
+        assert_eq!(sharded.shard(1).get_compression_value(), -90);
+        assert_eq!(
+            sharded.shard(2).get_compression_value(),
+            INITIAL_COMPRESSION_VALUE
+        );
+
+        let view = sharded.snapshot();
+        assert!(sharded.consolidate());
+        assert_eq!(sharded.consolidations(), 1);
+        for index in 0..4 {
+            let shard = sharded.shard(index).snapshot();
+            assert_eq!(shard.compression_value, -120);
+            // Three blocks of 1000 and one of 2000.
+            assert_eq!(shard.uncompressed_average, 1250.0);
+            assert_eq!(shard.compressed_average, 0.0);
+        }
+        assert_eq!(view, sharded.shard(3).snapshot());
+
+        // Without new blocks the global view is stable.
+        assert_eq!(sharded.snapshot(), sharded.shard(0).snapshot());
+    }
+
+    #[test]
+    fn test_consolidation_clamps_to_bounds() {
+        let sharded = manual(2);
+        for _ in 0..100 {
+            sharded.update_compression_ratio_on(0, 1000, 1000);
+            sharded.update_compression_ratio_on(1, 1000, 1000);
+        }
+        sharded.consolidate();
+        assert_eq!(sharded.snapshot().compression_value, MAX_COMPRESSION_VALUE);
+        assert!(sharded.should_skip_compression_on(1, 1000));
+    }
+
+    #[test]
+    fn test_automatic_consolidation_interval() {
+        let sharded = ShardedMVCompression::with_sharding(ShardingConfig {
+            shards: 3,
+            consolidation_interval: 5,
+        });
+        for _ in 0..4 {
+            sharded.update_compression_ratio_on(2, 1000, 1000);
+        }
+        assert_eq!(sharded.consolidations(), 0);
+        sharded.update_compression_ratio_on(2, 1000, 1000);
+        assert_eq!(sharded.consolidations(), 1);
+        assert_eq!(
+            sharded.shard(0).get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + 5 * NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+        assert!(sharded.try_update_on(0, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_reset_and_force_apply_to_every_shard() {
+        let sharded = manual(3);
+        sharded.update_compression_ratio_on(0, 1000, 1000);
+        sharded.update_compression_ratio_on(1, 0, 1000);
+
+        assert_eq!(sharded.force_value(500), MAX_COMPRESSION_VALUE);
+        for index in 0..3 {
+            assert_eq!(
+                sharded.shard(index).get_compression_value(),
+                MAX_COMPRESSION_VALUE
+            );
+        }
+        assert_eq!(sharded.snapshot().compression_value, MAX_COMPRESSION_VALUE);
+
+        sharded.reset();
+        let snapshot = sharded.snapshot();
+        assert_eq!(snapshot.compression_value, INITIAL_COMPRESSION_VALUE);
+        assert!(!snapshot.has_samples());
+
+        // Consolidation starts from the reset baseline.
+        sharded.update_compression_ratio_on(2, 1000, 1000);
+        sharded.consolidate();
+        assert_eq!(
+            sharded.shard(0).get_compression_value(),
+            INITIAL_COMPRESSION_VALUE + NON_COMPRESSIBLE_BLOCK_WEIGHT
+        );
+    }
+
+    #[test]
+    fn test_mode_applies_to_every_shard() {
+        let sharded = manual(3);
+        sharded.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: false,
+        });
+        assert!((0..3).all(|index| sharded.should_skip_compression_on(index, 1000)));
+        assert_eq!(
+            sharded.set_mode(PolicyMode::Adaptive),
+            PolicyMode::NeverCompress {
+                record_outcomes: false
+            }
+        );
+        assert!((0..3).all(|index| !sharded.should_skip_compression_on(index, 1000)));
+    }
+
+    #[test]
+    fn test_concurrent_shards_converge() {
+        let sharded = Arc::new(ShardedMVCompression::with_sharding(ShardingConfig {
+            shards: 4,
+            consolidation_interval: 16,
+        }));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let sharded = Arc::clone(&sharded);
+                thread::spawn(move || {
+                    let mut skipped = 0;
+                    for _ in 0..500 {
+                        if sharded.should_skip_compression(1000) {
+                            skipped += 1;
+                        } else {
+                            sharded.update_compression_ratio(1000, 1000);
+                        }
+                    }
+                    skipped
+                })
+            })
+            .collect();
+        let skipped: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert!(skipped > 0);
+
+        // Skips pull the score back down, so it hovers around the threshold.
+        sharded.consolidate();
+        let snapshot = sharded.snapshot();
+        assert!(snapshot.compression_value > INITIAL_COMPRESSION_VALUE);
+        assert_eq!(snapshot.uncompressed_average, 1000.0);
+        assert!(sharded.consolidations() > 1);
+    }
+
+    #[test]
+    fn test_consolidation_keeps_concurrent_score_changes() {
+        let sharded = Arc::new(
+            MVCompression::builder()
+                .config(MVCompressionConfig {
+                    min_compression_value: -1_000_000,
+                    ..MVCompressionConfig::default()
+                })
+                .build_sharded(ShardingConfig {
+                    shards: 4,
+                    consolidation_interval: 0,
+                }),
+        );
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let consolidator = {
+            let sharded = Arc::clone(&sharded);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    sharded.consolidate();
+                }
+            })
+        };
+        let writers: Vec<_> = (0..4)
+            .map(|index| {
+                let sharded = Arc::clone(&sharded);
+                thread::spawn(move || {
+                    for _ in 0..2000 {
+                        sharded.update_compression_ratio_on(index, 500, 1000);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        consolidator.join().unwrap();
+
+        sharded.consolidate();
+        let expected = INITIAL_COMPRESSION_VALUE + 4 * 2000 * COMPRESSIBLE_BLOCK_WEIGHT;
+        for index in 0..4 {
+            assert_eq!(sharded.shard(index).get_compression_value(), expected);
+        }
+    }
+
+    #[test]
+    fn test_histograms_add_up_shards() {
+        let sharded = MVCompression::builder()
+            .config(MVCompressionConfig {
+                histograms: true,
+                ..MVCompressionConfig::default()
+            })
+            .build_sharded(ShardingConfig::with_shards(3));
+        for index in 0..3 {
+            sharded.update_compression_ratio_on(index, 500, 1000);
+        }
+        assert_eq!(sharded.histograms().unwrap().ratio.count(), 3);
+    }
+}
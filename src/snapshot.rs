@@ -0,0 +1,173 @@
+//! Point-in-time views of the learned state.
+
+use crate::average::AVERAGE_FRACTION_BITS;
+
+/// The learned state of an instance at one point in time.
+///
+/// Each field is read atomically, but fields are read one after another, so a
+/// snapshot taken while other threads update the instance may combine values
+/// from neighbouring updates. Averages are zero before the first block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// Compression value; positive values enable skipping.
+    pub compression_value: i32,
+    /// Moving average of compressed block sizes, in bytes.
+    pub compressed_average: f64,
+    /// Moving average of uncompressed block sizes, in bytes.
+    pub uncompressed_average: f64,
+    /// Moving average of per-block compression ratios.
+    pub ratio_average: f64,
+    /// Number of regime changes detected so far.
+    pub regime_changes: u64,
+}
+
+impl Snapshot {
+    /// Returns true if the averages have seen at least one block.
+    ///
+    /// Validated blocks are never empty, so a zero uncompressed average means
+    /// that no block has been recorded.
+    pub fn has_samples(&self) -> bool {
+        self.uncompressed_average > 0.0
+    }
+
+    /// Combines weighted snapshots into one, e.g. the learned states of a fleet.
+    ///
+    /// The score and averages are weighted means; averages only count snapshots
+    /// that have samples. `regime_changes` is the sum over all snapshots with a
+    /// positive weight. Weights that are negative, zero or not finite are
+    /// ignored, and `None` is returned if no weight remains.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use mvcompression::{MVCompression, Snapshot};
+    ///
+    /// let text = MVCompression::new();
+    /// text.update_compression_ratio(250, 1000);
+    /// let random = MVCompression::new();
+    /// random.update_compression_ratio(1000, 1000);
+    ///
+    /// let consensus =
+    ///     Snapshot::combine([(text.snapshot(), 1.0), (random.snapshot(), 1.0)]).unwrap();
+    /// assert_eq!(consensus.compression_value, -83);
+    /// assert_eq!(consensus.compressed_average, 625.0);
+    /// ```
+    pub fn combine<I>(snapshots: I) -> Option<Snapshot>
+    where
+        I: IntoIterator<Item = (Snapshot, f64)>,
+    {
+        let mut total = 0.0;
+        let mut score = 0.0;
+        let mut sampled = 0.0;
+        let mut compressed = 0.0;
+        let mut uncompressed = 0.0;
+        let mut ratio = 0.0;
+        let mut regime_changes = 0u64;
+
+        for (snapshot, weight) in snapshots {
+            if !(weight.is_finite() && weight > 0.0) {
+                continue;
+            }
+            total += weight;
+            score += weight * f64::from(snapshot.compression_value);
+            regime_changes = regime_changes.saturating_add(snapshot.regime_changes);
+            if snapshot.has_samples() {
+                sampled += weight;
+                compressed += weight * snapshot.compressed_average;
+                uncompressed += weight * snapshot.uncompressed_average;
+                ratio += weight * snapshot.ratio_average;
+            }
+        }
+        if total <= 0.0 {
+            return None;
+        }
+
+        let sampled = if sampled > 0.0 { sampled } else { 1.0 };
+        Some(Snapshot {
+            compression_value: round_score(score / total),
+            compressed_average: compressed / sampled,
+            uncompressed_average: uncompressed / sampled,
+            ratio_average: ratio / sampled,
+            regime_changes,
+        })
+    }
+}
+
+/// Rounds a score to the nearest integer, half away from zero.
+fn round_score(score: f64) -> i32 {
+    let rounded = if score >= 0.0 {
+        score + 0.5
+    } else {
+        score - 0.5
+    };
+    // Float-to-int casts saturate.
+    rounded as i32
+}
+
+/// Converts an average in bytes (or ratio units) back to fixed point.
+pub(crate) fn to_fixed(value: f64) -> u64 {
+    (value.max(0.0) * (1u64 << AVERAGE_FRACTION_BITS) as f64 + 0.5) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(compression_value: i32, uncompressed_average: f64, ratio_average: f64) -> Snapshot {
+        Snapshot {
+            compression_value,
+            compressed_average: uncompressed_average * ratio_average,
+            uncompressed_average,
+            ratio_average,
+            regime_changes: 1,
+        }
+    }
+
+    #[test]
+    fn test_combine_weights_scores_and_averages() {
+        let combined = Snapshot::combine([
+            (snapshot(100, 1000.0, 1.0), 1.0),
+            (snapshot(-100, 2000.0, 0.5), 3.0),
+        ])
+        .unwrap();
+        assert_eq!(combined.compression_value, -50);
+        assert_eq!(combined.uncompressed_average, 1750.0);
+        assert_eq!(combined.compressed_average, 1000.0);
+        assert_eq!(combined.ratio_average, 0.625);
+        assert_eq!(combined.regime_changes, 2);
+    }
+
+    #[test]
+    fn test_combine_skips_empty_averages_and_bad_weights() {
+        let empty = Snapshot {
+            compression_value: -80,
+            compressed_average: 0.0,
+            uncompressed_average: 0.0,
+            ratio_average: 0.0,
+            regime_changes: 0,
+        };
+        let combined = Snapshot::combine([
+            (empty, 1.0),
+            (snapshot(-40, 500.0, 0.5), 1.0),
+            (snapshot(200, 9000.0, 1.0), -1.0),
+            (snapshot(200, 9000.0, 1.0), f64::NAN),
+        ])
+        .unwrap();
+        assert_eq!(combined.compression_value, -60);
+        assert_eq!(combined.uncompressed_average, 500.0);
+
+        assert_eq!(Snapshot::combine([(empty, 0.0)]), None);
+        assert_eq!(Snapshot::combine([(empty, 2.0)]), Some(empty));
+    }
+
+    #[test]
+    fn test_combine_rounds_half_away_from_zero() {
+        let half = |a, b| {
+            Snapshot::combine([(snapshot(a, 1.0, 1.0), 1.0), (snapshot(b, 1.0, 1.0), 1.0)])
+                .unwrap()
+                .compression_value
+        };
+        assert_eq!(half(0, 1), 1);
+        assert_eq!(half(0, -1), -1);
+        assert_eq!(half(i32::MAX, i32::MAX), i32::MAX);
+    }
+}
@@ -0,0 +1,87 @@
+//! Counters describing how an instance has behaved.
+
+use crate::atomic::{AtomicU64, Ordering};
+use crate::mode::SkipState;
+
+/// Decision statistics of an instance, returned by
+/// [`MVCompression::stats`](crate::MVCompression::stats).
+///
+/// All counts are cumulative over the lifetime of the instance; resets and
+/// forced values do not clear them. Decisions, outcomes and bytes saved are
+/// only counted while [`MVCompressionConfig::counters`](crate::MVCompressionConfig::counters)
+/// is set and stay zero otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PolicyStats {
+    /// Current skip state.
+    pub skip_state: SkipState,
+    /// Number of times the instance started skipping.
+    pub skip_entered: u64,
+    /// Number of times the instance went back to compressing.
+    pub skip_exited: u64,
+    /// Number of decisions that skipped compression.
+    pub skipped: u64,
+    /// Number of decisions that compressed.
+    pub attempted: u64,
+    /// Number of compression results accepted, whether or not they were
+    /// learned from.
+    pub outcomes: u64,
+    /// Sum of `uncompressed - compressed` over accepted results that shrank.
+    pub bytes_saved: u64,
+}
+
+impl PolicyStats {
+    /// Returns the total number of skip state transitions.
+    pub fn transitions(&self) -> u64 {
+        self.skip_entered + self.skip_exited
+    }
+
+    /// Returns the total number of decisions.
+    pub fn decisions(&self) -> u64 {
+        self.skipped + self.attempted
+    }
+}
+
+/// Decision and outcome counters, bumped on every call when enabled.
+#[derive(Debug)]
+pub(crate) struct Counters {
+    skipped: AtomicU64,
+    attempted: AtomicU64,
+    outcomes: AtomicU64,
+    bytes_saved: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            skipped: AtomicU64::new(0),
+            attempted: AtomicU64::new(0),
+            outcomes: AtomicU64::new(0),
+            bytes_saved: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record_decision(&self, skip: bool) {
+        let counter = if skip { &self.skipped } else { &self.attempted };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_outcomes(&self, outcomes: u64, bytes_saved: u64) {
+        self.outcomes.fetch_add(outcomes, Ordering::Relaxed);
+        if bytes_saved > 0 {
+            self.bytes_saved.fetch_add(bytes_saved, Ordering::Relaxed);
+        }
+    }
+
+    /// Fills in the counter fields of `stats`.
+    pub(crate) fn read_into(&self, stats: &mut PolicyStats) {
+        stats.skipped = self.skipped.load(Ordering::Relaxed);
+        stats.attempted = self.attempted.load(Ordering::Relaxed);
+        stats.outcomes = self.outcomes.load(Ordering::Relaxed);
+        stats.bytes_saved = self.bytes_saved.load(Ordering::Relaxed);
+    }
+}
+
+/// Bytes a compression result saved; expansions save nothing.
+pub(crate) fn saved_bytes(compressed: usize, uncompressed: usize) -> u64 {
+    uncompressed.saturating_sub(compressed) as u64
+}
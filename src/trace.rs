@@ -0,0 +1,338 @@
+//! `tracing` instrumentation of decisions and outcomes.
+//!
+//! With the `tracing` feature every decision and every recorded outcome can be
+//! emitted as a `tracing` event, so existing pipelines show why a block was or
+//! was not compressed. [`TraceConfig`] selects the level of each kind of event
+//! and how many of them are sampled. Events are only built when a subscriber
+//! is interested in the level, so instrumentation costs one level check while
+//! nobody listens. Without the feature the configuration is accepted but
+//! nothing is emitted.
+//!
+//! Decision events use the target `mvcompression::decision` and carry the
+//! fields `skip`, `reason`, `datasize`, `score_before` and `score_after`.
+//! Outcome events use the target `mvcompression::outcome` and carry
+//! `compressed`, `uncompressed`, `ratio`, `poorly_compressed`, `score_before`
+//! and `score_after`; merged batches report `blocks` and
+//! `poorly_compressed_blocks` instead of individual sizes.
+
+#[cfg(feature = "tracing")]
+use crate::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "tracing")]
+use crate::decision::Decision;
+
+/// Verbosity of an instrumentation event, mirroring `tracing::Level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceLevel {
+    /// `tracing::Level::ERROR`.
+    Error,
+    /// `tracing::Level::WARN`.
+    Warn,
+    /// `tracing::Level::INFO`.
+    Info,
+    /// `tracing::Level::DEBUG`.
+    Debug,
+    /// `tracing::Level::TRACE`.
+    Trace,
+}
+
+/// Which events the `tracing` feature emits, and how often.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::{MVCompressionConfig, TraceConfig, TraceLevel};
+///
+/// // Log outcomes at INFO, but only one in 100 of them.
+/// let config = MVCompressionConfig {
+///     trace: TraceConfig {
+///         decisions: None,
+///         outcomes: Some(TraceLevel::Info),
+///         sample_every: 100,
+///     },
+///     ..MVCompressionConfig::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Level of decision events; `None` disables them.
+    pub decisions: Option<TraceLevel>,
+    /// Level of outcome events; `None` disables them.
+    pub outcomes: Option<TraceLevel>,
+    /// Emit one in `sample_every` events of each kind. `0` and `1` emit all.
+    pub sample_every: u64,
+}
+
+impl TraceConfig {
+    /// Decisions at TRACE and outcomes at DEBUG, without sampling.
+    pub const fn new() -> Self {
+        Self {
+            decisions: Some(TraceLevel::Trace),
+            outcomes: Some(TraceLevel::Debug),
+            sample_every: 1,
+        }
+    }
+
+    /// Emits nothing.
+    pub const fn disabled() -> Self {
+        Self {
+            decisions: None,
+            outcomes: None,
+            sample_every: 1,
+        }
+    }
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Expands to one arm per level, since `tracing` levels must be constants.
+#[cfg(feature = "tracing")]
+macro_rules! at_level {
+    ($level:expr, $macro:ident!($($args:tt)*)) => {
+        match $level {
+            TraceLevel::Error => tracing::$macro!($($args)* tracing::Level::ERROR),
+            TraceLevel::Warn => tracing::$macro!($($args)* tracing::Level::WARN),
+            TraceLevel::Info => tracing::$macro!($($args)* tracing::Level::INFO),
+            TraceLevel::Debug => tracing::$macro!($($args)* tracing::Level::DEBUG),
+            TraceLevel::Trace => tracing::$macro!($($args)* tracing::Level::TRACE),
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! event_at {
+    ($level:expr, target: $target:expr, $($fields:tt)*) => {
+        match $level {
+            TraceLevel::Error => tracing::event!(target: $target, tracing::Level::ERROR, $($fields)*),
+            TraceLevel::Warn => tracing::event!(target: $target, tracing::Level::WARN, $($fields)*),
+            TraceLevel::Info => tracing::event!(target: $target, tracing::Level::INFO, $($fields)*),
+            TraceLevel::Debug => tracing::event!(target: $target, tracing::Level::DEBUG, $($fields)*),
+            TraceLevel::Trace => tracing::event!(target: $target, tracing::Level::TRACE, $($fields)*),
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+const DECISION_TARGET: &str = "mvcompression::decision";
+#[cfg(feature = "tracing")]
+const OUTCOME_TARGET: &str = "mvcompression::outcome";
+
+/// Per-instance sampling state for instrumentation events.
+#[cfg(feature = "tracing")]
+#[derive(Debug)]
+pub(crate) struct Tracer {
+    decisions: AtomicU64,
+    outcomes: AtomicU64,
+}
+
+#[cfg(feature = "tracing")]
+impl Tracer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            decisions: AtomicU64::new(0),
+            outcomes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the level to emit a decision event at, if one is due.
+    pub(crate) fn decision_level(&self, config: &TraceConfig) -> Option<TraceLevel> {
+        let level = config.decisions?;
+        let enabled = at_level!(level, enabled!(target: DECISION_TARGET,));
+        (enabled && sample(&self.decisions, config.sample_every)).then_some(level)
+    }
+
+    /// Returns the level to emit an outcome event at, if one is due.
+    pub(crate) fn outcome_level(&self, config: &TraceConfig) -> Option<TraceLevel> {
+        let level = config.outcomes?;
+        let enabled = at_level!(level, enabled!(target: OUTCOME_TARGET,));
+        (enabled && sample(&self.outcomes, config.sample_every)).then_some(level)
+    }
+
+    pub(crate) fn decision(
+        level: TraceLevel,
+        datasize: usize,
+        decision: Decision,
+        score_before: i32,
+        score_after: i32,
+    ) {
+        event_at!(
+            level,
+            target: DECISION_TARGET,
+            skip = decision.skip,
+            reason = ?decision.reason,
+            datasize,
+            score_before,
+            score_after,
+            "compression decision"
+        );
+    }
+
+    pub(crate) fn outcome(
+        level: TraceLevel,
+        compressed: usize,
+        uncompressed: usize,
+        poorly_compressed: bool,
+        score_before: i32,
+        score_after: i32,
+    ) {
+        let ratio = compressed as f64 / uncompressed as f64;
+        event_at!(
+            level,
+            target: OUTCOME_TARGET,
+            compressed,
+            uncompressed,
+            ratio,
+            poorly_compressed,
+            score_before,
+            score_after,
+            "compression outcome"
+        );
+    }
+
+    pub(crate) fn batch(
+        level: TraceLevel,
+        blocks: u64,
+        poorly_compressed_blocks: u64,
+        score_before: i32,
+        score_after: i32,
+    ) {
+        event_at!(
+            level,
+            target: OUTCOME_TARGET,
+            blocks,
+            poorly_compressed_blocks,
+            score_before,
+            score_after,
+            "compression outcome batch"
+        );
+    }
+}
+
+/// Returns true for one in `every` calls. The counter is only touched once a
+/// subscriber is known to listen.
+#[cfg(feature = "tracing")]
+fn sample(counter: &AtomicU64, every: u64) -> bool {
+    every <= 1
+        || counter
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(every)
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::config::MVCompressionConfig;
+    use crate::mvcompression::MVCompression;
+    use std::fmt::Write;
+    use std::string::String;
+    use std::sync::{Arc, Mutex};
+    use std::vec::Vec;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::subscriber::Interest;
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    /// Records events up to `max_level` as `target: field=value ...` lines.
+    struct Capture {
+        max_level: Level,
+        lines: Mutex<Vec<String>>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+            Interest::sometimes()
+        }
+
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            *metadata.level() <= self.max_level
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::from(event.metadata().target()));
+            event.record(&mut fields);
+            self.lines.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn capture(
+        max_level: Level,
+        config: TraceConfig,
+        run: impl FnOnce(&MVCompression),
+    ) -> Vec<String> {
+        let subscriber = Arc::new(Capture {
+            max_level,
+            lines: Mutex::new(Vec::new()),
+        });
+        let mvc = MVCompression::with_config(MVCompressionConfig {
+            trace: config,
+            ..MVCompressionConfig::default()
+        });
+        tracing::subscriber::with_default(Arc::clone(&subscriber), || run(&mvc));
+        let lines = subscriber.lines.lock().unwrap().clone();
+        lines
+    }
+
+    #[test]
+    fn test_decisions_and_outcomes_are_traced() {
+        let lines = capture(Level::TRACE, TraceConfig::new(), |mvc| {
+            mvc.should_skip_compression(1000);
+            mvc.update_compression_ratio(250, 1000);
+        });
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("mvcompression::decision"));
+        assert!(lines[0].contains("skip=false"));
+        assert!(lines[0].contains("reason=Compressing"));
+        assert!(lines[0].contains("score_before=-80"));
+        assert!(lines[1].starts_with("mvcompression::outcome"));
+        assert!(lines[1].contains("ratio=0.25"));
+        assert!(lines[1].contains("score_before=-80 score_after=-90"));
+    }
+
+    #[test]
+    fn test_levels_and_sampling_filter_events() {
+        let config = TraceConfig {
+            decisions: Some(TraceLevel::Trace),
+            outcomes: Some(TraceLevel::Info),
+            sample_every: 10,
+        };
+        let lines = capture(Level::INFO, config, |mvc| {
+            for _ in 0..100 {
+                mvc.should_skip_compression(1000);
+                mvc.update_compression_ratio(500, 1000);
+            }
+        });
+        // Decisions are below the subscriber's level; one in ten outcomes remains.
+        assert_eq!(lines.len(), 10);
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with("mvcompression::outcome")));
+
+        let lines = capture(Level::TRACE, TraceConfig::disabled(), |mvc| {
+            mvc.should_skip_compression(1000);
+            mvc.update_compression_ratio(500, 1000);
+        });
+        assert!(lines.is_empty());
+    }
+}
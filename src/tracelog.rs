@@ -0,0 +1,651 @@
+//! Recording every decision and result to a compact binary trace.
+//!
+//! A [`TraceRecorder`] is an [`Observer`] that receives a [`TraceEntry`] for
+//! every decision and accepted result of the instance it is installed on. It
+//! either keeps the most recent entries in a bounded in-memory ring, or appends
+//! them to a trace file (any [`Write`]r). Recording can be switched on and off
+//! at runtime; while it is off, the instance skips building entries and only
+//! checks one flag. While it is on, every entry takes the recorder's one lock,
+//! which all threads share, so recording serializes concurrent callers.
+//!
+//! # Format
+//!
+//! A trace starts with an 8-byte header: the magic bytes `MVCT`, the format
+//! version ([`FORMAT_VERSION`]) and three zero bytes. Entries follow without
+//! padding. Integers are LEB128 varints; signed integers are zigzag-encoded
+//! first. Each entry is:
+//!
+//! | Field | Encoding |
+//! |-------|----------|
+//! | tag | one byte: bits 0-1 kind (`0` decision, `1` outcome, `2` batch); for decisions bit 2 is set when skipped and bits 3-5 hold the reason |
+//! | timestamp | signed varint, nanoseconds since the previous entry's timestamp (since `0` for the first) |
+//! | decision | size varint, score signed varint |
+//! | outcome | compressed varint, uncompressed varint, score signed varint |
+//! | batch | blocks varint, poorly compressed blocks varint, score signed varint |
+//!
+//! Reasons are numbered `0` overridden, `1` compressing, `2` outside window,
+//! `3` probe and `4` skipped. A typical decision takes 6 to 9 bytes. Entries
+//! from concurrent threads are written in the order they reach the recorder,
+//! so timestamps can step backwards slightly. [`TraceReader`] decodes a trace.
+
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+use crate::decision::{Decision, DecisionReason};
+use crate::observer::{Event, Observer, TraceEntry};
+
+/// Magic bytes at the start of every trace.
+pub const MAGIC: [u8; 4] = *b"MVCT";
+
+/// Version of the trace format written by this crate.
+pub const FORMAT_VERSION: u8 = 1;
+
+const KIND_DECISION: u8 = 0;
+const KIND_OUTCOME: u8 = 1;
+const KIND_BATCH: u8 = 2;
+const KIND_MASK: u8 = 0b11;
+const SKIP_FLAG: u8 = 0b100;
+const REASON_SHIFT: u32 = 3;
+
+/// Records decisions and results of the instance it observes.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::tracelog::{TraceReader, TraceRecorder};
+/// use mvcompression::{MVCompression, TraceEntry};
+///
+/// let recorder = TraceRecorder::ring(1024);
+/// let mvc = MVCompression::builder().observer(&recorder).build();
+/// mvc.should_skip_compression(4096);
+/// mvc.update_compression_ratio(1024, 4096);
+///
+/// let entries = recorder.entries();
+/// assert_eq!(entries.len(), 2);
+/// assert!(matches!(entries[1], TraceEntry::Outcome { compressed: 1024, score: -90, .. }));
+///
+/// // The ring can be saved in the binary format and read back.
+/// let mut trace = Vec::new();
+/// recorder.write_to(&mut trace).unwrap();
+/// let decoded: Vec<TraceEntry> = TraceReader::new(&trace[..])
+///     .unwrap()
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(decoded, entries);
+/// ```
+#[derive(Debug)]
+pub struct TraceRecorder {
+    enabled: AtomicBool,
+    sink: Mutex<Sink>,
+}
+
+enum Sink {
+    Ring {
+        entries: VecDeque<TraceEntry>,
+        capacity: usize,
+    },
+    Writer {
+        writer: TraceWriter<Box<dyn Write + Send>>,
+        /// The first write error since the last flush; recording pauses
+        /// until it has been reported.
+        error: Option<io::Error>,
+    },
+}
+
+impl core::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Sink::Ring { entries, capacity } => f
+                .debug_struct("Ring")
+                .field("len", &entries.len())
+                .field("capacity", capacity)
+                .finish(),
+            Sink::Writer { error, .. } => f.debug_struct("Writer").field("error", error).finish(),
+        }
+    }
+}
+
+impl TraceRecorder {
+    /// Returns an enabled recorder that keeps the last `capacity` entries in
+    /// memory.
+    pub fn ring(capacity: usize) -> Self {
+        Self::with_sink(Sink::Ring {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    /// Returns an enabled recorder that appends every entry to `writer`, after
+    /// writing the header.
+    ///
+    /// Wrap unbuffered writers in a [`BufWriter`]; call
+    /// [`flush`](Self::flush) before reading the trace.
+    pub fn writer(writer: impl Write + Send + 'static) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        Ok(Self::with_sink(Sink::Writer {
+            writer: TraceWriter::new(writer)?,
+            error: None,
+        }))
+    }
+
+    /// Returns an enabled recorder that writes a new trace file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::writer(BufWriter::new(File::create(path)?))
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        Self {
+            enabled: AtomicBool::new(true),
+            sink: Mutex::new(sink),
+        }
+    }
+
+    /// Switches recording on or off and returns the previous setting.
+    pub fn set_enabled(&self, enabled: bool) -> bool {
+        self.enabled.swap(enabled, Ordering::Relaxed)
+    }
+
+    /// Returns true while entries are recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Returns the entries in the ring, oldest first; empty for a recorder
+    /// writing to a file.
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        match &*self.lock() {
+            Sink::Ring { entries, .. } => entries.iter().copied().collect(),
+            Sink::Writer { .. } => Vec::new(),
+        }
+    }
+
+    /// Writes the entries in the ring as a complete trace, header included.
+    pub fn write_to(&self, writer: impl Write) -> io::Result<()> {
+        let mut writer = TraceWriter::new(writer)?;
+        for entry in self.entries() {
+            writer.write(&entry)?;
+        }
+        writer.flush()
+    }
+
+    /// Flushes a recorder writing to a file, or reports the first write error
+    /// that paused recording since the last call and resumes it.
+    pub fn flush(&self) -> io::Result<()> {
+        match &mut *self.lock() {
+            Sink::Ring { .. } => Ok(()),
+            Sink::Writer { writer, error } => match error.take() {
+                Some(error) => Err(error),
+                None => writer.flush(),
+            },
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Sink> {
+        self.sink.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Observer for TraceRecorder {
+    const ENABLED: bool = false;
+
+    fn on_event(&self, _event: &Event) {}
+
+    fn wants_trace(&self) -> bool {
+        self.is_enabled()
+    }
+
+    fn on_trace(&self, entry: &TraceEntry) {
+        match &mut *self.lock() {
+            Sink::Ring { entries, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(*entry);
+            }
+            Sink::Writer { writer, error } => {
+                if error.is_none() {
+                    *error = writer.write(entry).err();
+                }
+            }
+        }
+    }
+}
+
+/// Encodes entries in the trace format.
+#[derive(Debug)]
+pub struct TraceWriter<W> {
+    inner: W,
+    previous_nanos: u64,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Writes the header to `inner` and returns a writer for the entries.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(&MAGIC)?;
+        inner.write_all(&[FORMAT_VERSION, 0, 0, 0])?;
+        Ok(Self {
+            inner,
+            previous_nanos: 0,
+            buffer: Vec::with_capacity(32),
+        })
+    }
+
+    /// Appends one entry.
+    pub fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        let buffer = &mut self.buffer;
+        buffer.clear();
+        let nanos = entry.timestamp_nanos();
+        let delta = nanos.wrapping_sub(self.previous_nanos) as i64;
+        match *entry {
+            TraceEntry::Decision { size, decision, .. } => {
+                let skip = if decision.skip { SKIP_FLAG } else { 0 };
+                buffer.push(KIND_DECISION | skip | reason_code(decision.reason) << REASON_SHIFT);
+                put_signed(buffer, delta);
+                put_varint(buffer, size as u64);
+            }
+            TraceEntry::Outcome {
+                compressed,
+                uncompressed,
+                ..
+            } => {
+                buffer.push(KIND_OUTCOME);
+                put_signed(buffer, delta);
+                put_varint(buffer, compressed as u64);
+                put_varint(buffer, uncompressed as u64);
+            }
+            TraceEntry::Batch {
+                blocks,
+                poorly_compressed,
+                ..
+            } => {
+                buffer.push(KIND_BATCH);
+                put_signed(buffer, delta);
+                put_varint(buffer, blocks);
+                put_varint(buffer, poorly_compressed);
+            }
+        }
+        put_signed(buffer, i64::from(entry.score()));
+        self.inner.write_all(buffer)?;
+        self.previous_nanos = nanos;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Decodes a trace, yielding its entries in order. The input is buffered
+/// internally.
+#[derive(Debug)]
+pub struct TraceReader<R> {
+    inner: BufReader<R>,
+    previous_nanos: u64,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Reads and checks the header of the trace in `inner`.
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut inner = BufReader::new(inner);
+        let mut header = [0; 8];
+        inner.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid("not a trace"));
+        }
+        if header[4] != FORMAT_VERSION {
+            return Err(invalid("unsupported trace format version"));
+        }
+        Ok(Self {
+            inner,
+            previous_nanos: 0,
+        })
+    }
+
+    fn read_entry(&mut self, tag: u8) -> io::Result<TraceEntry> {
+        let delta = self.signed()?;
+        let timestamp_nanos = self.previous_nanos.wrapping_add(delta as u64);
+        self.previous_nanos = timestamp_nanos;
+        let entry = match tag & KIND_MASK {
+            KIND_DECISION => {
+                let reason = decode_reason(tag >> REASON_SHIFT)?;
+                let size = self.size()?;
+                TraceEntry::Decision {
+                    timestamp_nanos,
+                    size,
+                    decision: Decision {
+                        skip: tag & SKIP_FLAG != 0,
+                        reason,
+                    },
+                    score: self.score()?,
+                }
+            }
+            KIND_OUTCOME => TraceEntry::Outcome {
+                timestamp_nanos,
+                compressed: self.size()?,
+                uncompressed: self.size()?,
+                score: self.score()?,
+            },
+            KIND_BATCH => TraceEntry::Batch {
+                timestamp_nanos,
+                blocks: self.varint()?,
+                poorly_compressed: self.varint()?,
+                score: self.score()?,
+            },
+            _ => return Err(invalid("unknown entry kind")),
+        };
+        Ok(entry)
+    }
+
+    /// Reads one byte; `None` at the end of the input.
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.inner.fill_buf() {
+                Ok(buf) => {
+                    let byte = buf.first().copied();
+                    if byte.is_some() {
+                        self.inner.consume(1);
+                    }
+                    return Ok(byte);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self
+                .byte()?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn signed(&mut self) -> io::Result<i64> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn size(&mut self) -> io::Result<usize> {
+        usize::try_from(self.varint()?).map_err(|_| invalid("size out of range"))
+    }
+
+    fn score(&mut self) -> io::Result<i32> {
+        i32::try_from(self.signed()?).map_err(|_| invalid("score out of range"))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.byte() {
+            Ok(None) => None,
+            Ok(Some(tag)) => Some(self.read_entry(tag)),
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+fn reason_code(reason: DecisionReason) -> u8 {
+    match reason {
+        DecisionReason::Overridden => 0,
+        DecisionReason::Compressing => 1,
+        DecisionReason::OutsideWindow => 2,
+        DecisionReason::Probe => 3,
+        DecisionReason::Skipped => 4,
+    }
+}
+
+fn decode_reason(code: u8) -> io::Result<DecisionReason> {
+    Ok(match code {
+        0 => DecisionReason::Overridden,
+        1 => DecisionReason::Compressing,
+        2 => DecisionReason::OutsideWindow,
+        3 => DecisionReason::Probe,
+        4 => DecisionReason::Skipped,
+        _ => return Err(invalid("unknown decision reason")),
+    })
+}
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn put_signed(buffer: &mut Vec<u8>, value: i64) {
+    put_varint(buffer, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::config::MVCompressionConfig;
+    use crate::mode::PolicyMode;
+    use crate::mvcompression::MVCompression;
+    use std::sync::Arc;
+
+    fn decode(trace: &[u8]) -> Vec<TraceEntry> {
+        TraceReader::new(trace)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_every_entry_kind_round_trips() {
+        let entries = [
+            TraceEntry::Decision {
+                timestamp_nanos: 1_000,
+                size: 4096,
+                decision: Decision {
+                    skip: true,
+                    reason: DecisionReason::Skipped,
+                },
+                score: 7,
+            },
+            TraceEntry::Outcome {
+                timestamp_nanos: 900,
+                compressed: usize::MAX,
+                uncompressed: 1,
+                score: i32::MIN,
+            },
+            TraceEntry::Batch {
+                timestamp_nanos: u64::MAX,
+                blocks: 64,
+                poorly_compressed: 3,
+                score: i32::MAX,
+            },
+        ];
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        for entry in &entries {
+            writer.write(entry).unwrap();
+        }
+        let trace = writer.into_inner();
+        assert_eq!(&trace[..5], b"MVCT\x01");
+        assert_eq!(decode(&trace), entries);
+        // After the header: tag (skipped, reason 4), delta 1000, size 4096.
+        assert_eq!(trace[8..13], [36, 0xd0, 0x0f, 0x80, 0x20]);
+    }
+
+    #[test]
+    fn test_records_decisions_and_results_of_an_instance() {
+        let clock = ManualClock::new();
+        let recorder = TraceRecorder::ring(3);
+        let mvc = MVCompression::builder()
+            .clock(&clock)
+            .observer(&recorder)
+            .build();
+        mvc.should_skip_compression(1000);
+        clock.advance(std::time::Duration::from_nanos(5));
+        mvc.update_compression_ratio(250, 1000);
+        {
+            let mut local = mvc.local_recorder(2);
+            local.update_compression_ratio(250, 1000);
+            local.update_compression_ratio(250, 1000);
+        }
+        let _ = mvc.try_update(10, 0);
+
+        recorder.set_enabled(false);
+        mvc.should_skip_compression(1000);
+        recorder.set_enabled(true);
+        mvc.set_mode(PolicyMode::NeverCompress {
+            record_outcomes: false,
+        });
+        mvc.should_skip_compression(1000);
+
+        // The ring keeps the last three entries; rejected results and
+        // decisions made while disabled are not recorded.
+        let entries = recorder.entries();
+        assert_eq!(
+            entries,
+            [
+                TraceEntry::Outcome {
+                    timestamp_nanos: 5,
+                    compressed: 250,
+                    uncompressed: 1000,
+                    score: -90,
+                },
+                TraceEntry::Batch {
+                    timestamp_nanos: 5,
+                    blocks: 2,
+                    poorly_compressed: 0,
+                    score: -110,
+                },
+                TraceEntry::Decision {
+                    timestamp_nanos: 5,
+                    size: 1000,
+                    decision: Decision::skip(DecisionReason::Overridden),
+                    score: -110,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pairs_forward_events_and_entries() {
+        use crate::observer::{EventKind, FnObserver};
+        use std::sync::atomic::AtomicUsize;
+
+        let started = AtomicUsize::new(0);
+        let recorder = TraceRecorder::ring(16);
+        let events = FnObserver(|event: &Event| {
+            if event.kind == EventKind::SkipStarted {
+                started.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let mvc = MVCompression::builder()
+            .observer((events, &recorder))
+            .build();
+        mvc.force_value(1);
+        mvc.should_skip_compression(4096);
+
+        assert_eq!(started.load(Ordering::Relaxed), 1);
+        assert_eq!(recorder.entries().len(), 1);
+    }
+
+    #[test]
+    fn test_writer_appends_to_file() {
+        let path = std::env::temp_dir().join(format!("mvcompression-trace-{}", std::process::id()));
+        let recorder = Arc::new(TraceRecorder::create(&path).unwrap());
+        let mvc = MVCompression::builder()
+            .config(MVCompressionConfig::default())
+            .observer(Arc::clone(&recorder))
+            .build();
+        for _ in 0..100 {
+            if !mvc.should_skip_compression(1000) {
+                mvc.update_compression_ratio(990, 1000);
+            }
+        }
+        recorder.flush().unwrap();
+        assert!(recorder.entries().is_empty());
+
+        let trace = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let entries = decode(&trace);
+        assert!(entries.len() > 100);
+        assert_eq!(entries.last().unwrap().score(), mvc.get_compression_value());
+        assert!(trace.len() < 8 + 8 * entries.len());
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_truncated_input() {
+        assert_eq!(
+            TraceReader::new(&b"GZIP\x01\0\0\0"[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer
+            .write(&TraceEntry::Batch {
+                timestamp_nanos: 1 << 40,
+                blocks: 1,
+                poorly_compressed: 1,
+                score: 0,
+            })
+            .unwrap();
+        let trace = writer.into_inner();
+        let mut reader = TraceReader::new(&trace[..trace.len() - 2]).unwrap();
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_reads_are_buffered() {
+        struct CountingReader<'a> {
+            inner: &'a [u8],
+            reads: usize,
+        }
+        impl Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        let recorder = TraceRecorder::ring(1000);
+        let mvc = MVCompression::builder().observer(&recorder).build();
+        for _ in 0..1000 {
+            mvc.should_skip_compression(4096);
+        }
+        let mut trace = Vec::new();
+        recorder.write_to(&mut trace).unwrap();
+        let mut input = CountingReader {
+            inner: &trace,
+            reads: 0,
+        };
+        let entries = TraceReader::new(&mut input).unwrap().count();
+        assert_eq!(entries, 1000);
+        assert!(input.reads < 10, "{} reads", input.reads);
+    }
+}
@@ -0,0 +1,465 @@
+//! Searching the configuration space against recorded blocks.
+//!
+//! A [`Tuner`] replays the same blocks through a [`Simulator`] under many
+//! configurations and keeps the one that maximizes a user-supplied objective
+//! over the [`ReplayReport`], such as bytes saved minus the cost of running the
+//! compressor ([`net_savings`]). The search is a coordinate descent over a grid
+//! of values per [`Parameter`]: every round tries each value of each parameter
+//! while holding the others at their best so far, and the search stops after a
+//! round without improvement. Replays are deterministic, so the same blocks and
+//! grids always produce the same result.
+//!
+//! The [`Tuning`] result also reports, per parameter, the objective at every
+//! value of its grid around the best configuration, so flat parameters can be
+//! told apart from ones that matter.
+
+use std::vec::Vec;
+
+use crate::config::MVCompressionConfig;
+use crate::ratio::Ratio;
+use crate::replay::{ReplayBlock, ReplayReport, Simulator};
+
+/// A tunable field of [`MVCompressionConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Parameter {
+    /// [`MVCompressionConfig::compressible_ratio`], as a fraction.
+    CompressibleRatio,
+    /// [`MVCompressionConfig::compressible_block_weight`].
+    CompressibleBlockWeight,
+    /// [`MVCompressionConfig::non_compressible_block_weight`].
+    NonCompressibleBlockWeight,
+    /// [`MVCompressionConfig::skip_compression_block_weight`].
+    SkipCompressionBlockWeight,
+    /// [`MVCompressionConfig::min_compression_value`].
+    MinCompressionValue,
+    /// [`MVCompressionConfig::max_compression_value`].
+    MaxCompressionValue,
+    /// The upper side of [`MVCompressionConfig::skip_window`], as a fraction of
+    /// the expected size.
+    SkipWindowAbove,
+    /// [`MVCompressionConfig::smoothing_factor`].
+    SmoothingFactor,
+}
+
+impl Parameter {
+    /// Every parameter, in the order they are tuned by default.
+    pub const ALL: [Parameter; 8] = [
+        Parameter::CompressibleRatio,
+        Parameter::NonCompressibleBlockWeight,
+        Parameter::CompressibleBlockWeight,
+        Parameter::SkipCompressionBlockWeight,
+        Parameter::SkipWindowAbove,
+        Parameter::SmoothingFactor,
+        Parameter::MaxCompressionValue,
+        Parameter::MinCompressionValue,
+    ];
+
+    /// Returns the name of the configuration field.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Parameter::CompressibleRatio => "compressible_ratio",
+            Parameter::CompressibleBlockWeight => "compressible_block_weight",
+            Parameter::NonCompressibleBlockWeight => "non_compressible_block_weight",
+            Parameter::SkipCompressionBlockWeight => "skip_compression_block_weight",
+            Parameter::MinCompressionValue => "min_compression_value",
+            Parameter::MaxCompressionValue => "max_compression_value",
+            Parameter::SkipWindowAbove => "skip_window.above",
+            Parameter::SmoothingFactor => "smoothing_factor",
+        }
+    }
+
+    /// Returns the values tried unless [`Tuner::grid`] replaces them.
+    pub const fn default_grid(self) -> &'static [f64] {
+        match self {
+            Parameter::CompressibleRatio => &[0.5, 0.6, 0.7, 0.8, 0.85, 0.9, 0.95],
+            Parameter::CompressibleBlockWeight => &[-40.0, -20.0, -10.0, -5.0, -2.0],
+            Parameter::NonCompressibleBlockWeight => &[1.0, 2.0, 4.0, 8.0, 16.0],
+            Parameter::SkipCompressionBlockWeight => &[-4.0, -2.0, -1.0, 0.0],
+            Parameter::MinCompressionValue => &[-600.0, -300.0, -150.0, -80.0],
+            Parameter::MaxCompressionValue => &[50.0, 100.0, 200.0, 400.0],
+            Parameter::SkipWindowAbove => &[0.1, 0.25, 0.5, 1.0],
+            Parameter::SmoothingFactor => &[1.0, 2.0, 3.0, 4.0, 5.0],
+        }
+    }
+
+    /// Returns the parameter's value in `config`.
+    pub fn get(self, config: &MVCompressionConfig) -> f64 {
+        match self {
+            Parameter::CompressibleRatio => config.compressible_ratio.to_f64(),
+            Parameter::CompressibleBlockWeight => f64::from(config.compressible_block_weight),
+            Parameter::NonCompressibleBlockWeight => {
+                f64::from(config.non_compressible_block_weight)
+            }
+            Parameter::SkipCompressionBlockWeight => {
+                f64::from(config.skip_compression_block_weight)
+            }
+            Parameter::MinCompressionValue => f64::from(config.min_compression_value),
+            Parameter::MaxCompressionValue => f64::from(config.max_compression_value),
+            Parameter::SkipWindowAbove => config.skip_window.above.to_f64(),
+            Parameter::SmoothingFactor => f64::from(config.smoothing_factor),
+        }
+    }
+
+    /// Returns `config` with the parameter set to `value`.
+    ///
+    /// Fractions are rounded to thousandths and other values to integers.
+    pub fn set(self, config: MVCompressionConfig, value: f64) -> MVCompressionConfig {
+        let fraction = || Ratio::new((value.max(0.0) * 1000.0).round() as u64, 1000);
+        let integer = || value.round() as i32;
+        match self {
+            Parameter::CompressibleRatio => MVCompressionConfig {
+                compressible_ratio: fraction(),
+                ..config
+            },
+            Parameter::CompressibleBlockWeight => MVCompressionConfig {
+                compressible_block_weight: integer(),
+                ..config
+            },
+            Parameter::NonCompressibleBlockWeight => MVCompressionConfig {
+                non_compressible_block_weight: integer(),
+                ..config
+            },
+            Parameter::SkipCompressionBlockWeight => MVCompressionConfig {
+                skip_compression_block_weight: integer(),
+                ..config
+            },
+            Parameter::MinCompressionValue => MVCompressionConfig {
+                min_compression_value: integer(),
+                ..config
+            },
+            Parameter::MaxCompressionValue => MVCompressionConfig {
+                max_compression_value: integer(),
+                ..config
+            },
+            Parameter::SkipWindowAbove => {
+                let mut config = config;
+                config.skip_window.above = fraction();
+                config
+            }
+            Parameter::SmoothingFactor => MVCompressionConfig {
+                smoothing_factor: value.max(0.0).round() as u32,
+                ..config
+            },
+        }
+    }
+}
+
+/// Returns an objective scoring a replay by the bytes it saved minus
+/// `cost_per_byte` for every byte fed to the compressor.
+///
+/// A cost of `0.1` means compressing a block pays off once it saves more than
+/// 10% of its size.
+pub fn net_savings(cost_per_byte: f64) -> impl Fn(&ReplayReport) -> f64 + Copy {
+    move |report| report.bytes_saved as f64 - cost_per_byte * report.attempted_bytes as f64
+}
+
+/// Searches configurations for the one maximizing an objective over recorded
+/// blocks.
+///
+/// # Examples
+/// ```rust
+/// use mvcompression::replay::ReplayBlock;
+/// use mvcompression::tune::{net_savings, Tuner};
+///
+/// // Runs of compressible blocks between longer runs of incompressible ones.
+/// let blocks: Vec<_> = (0..2000)
+///     .map(|i| {
+///         if i % 500 < 100 {
+///             ReplayBlock::new(4096, 1024)
+///         } else {
+///             ReplayBlock::new(4096, 4000)
+///         }
+///     })
+///     .collect();
+///
+/// let tuning = Tuner::new(net_savings(0.05)).tune(&blocks);
+/// assert!(tuning.objective >= tuning.baseline_objective);
+/// for sensitivity in &tuning.sensitivity {
+///     println!("{}: best {} (spread {:.0})", sensitivity.parameter.name(), sensitivity.value, sensitivity.spread());
+/// }
+/// let config = tuning.config;
+/// # let _ = config;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Tuner<F> {
+    objective: F,
+    base: MVCompressionConfig,
+    simulator: Simulator,
+    grids: Vec<(Parameter, Vec<f64>)>,
+    max_rounds: usize,
+}
+
+impl<F: Fn(&ReplayReport) -> f64> Tuner<F> {
+    /// Returns a tuner maximizing `objective`, starting from the default
+    /// configuration and searching every parameter over its default grid for
+    /// at most three rounds.
+    pub fn new(objective: F) -> Self {
+        Self {
+            objective,
+            base: MVCompressionConfig::default(),
+            simulator: Simulator::new(),
+            grids: Parameter::ALL
+                .iter()
+                .map(|&parameter| (parameter, parameter.default_grid().to_vec()))
+                .collect(),
+            max_rounds: 3,
+        }
+    }
+
+    /// Sets the configuration the search starts from; fields that are not
+    /// tuned keep their values.
+    pub fn base(mut self, config: MVCompressionConfig) -> Self {
+        self.base = config;
+        self
+    }
+
+    /// Sets the simulator replaying the blocks, e.g. to change its seed.
+    pub fn simulator(mut self, simulator: Simulator) -> Self {
+        self.simulator = simulator;
+        self
+    }
+
+    /// Restricts the search to `parameters`, in this order, keeping their
+    /// grids.
+    pub fn parameters(mut self, parameters: &[Parameter]) -> Self {
+        self.grids = parameters
+            .iter()
+            .map(|&parameter| {
+                let grid = self
+                    .grids
+                    .iter()
+                    .find(|(tuned, _)| *tuned == parameter)
+                    .map_or_else(
+                        || parameter.default_grid().to_vec(),
+                        |(_, grid)| grid.clone(),
+                    );
+                (parameter, grid)
+            })
+            .collect();
+        self
+    }
+
+    /// Sets the values tried for `parameter`, adding it to the search if
+    /// needed.
+    pub fn grid(mut self, parameter: Parameter, values: &[f64]) -> Self {
+        match self.grids.iter_mut().find(|(tuned, _)| *tuned == parameter) {
+            Some((_, grid)) => *grid = values.to_vec(),
+            None => self.grids.push((parameter, values.to_vec())),
+        }
+        self
+    }
+
+    /// Sets the largest number of rounds over all parameters.
+    pub fn max_rounds(mut self, rounds: usize) -> Self {
+        self.max_rounds = rounds;
+        self
+    }
+
+    /// Searches for the best configuration over `blocks`.
+    ///
+    /// Configurations whose lower score bound is not below the upper one are
+    /// not tried.
+    pub fn tune(&self, blocks: &[ReplayBlock]) -> Tuning {
+        let mut trials = 0;
+        let mut evaluate = |config: MVCompressionConfig| {
+            trials += 1;
+            (self.objective)(&self.simulator.run(config, blocks.iter().copied()))
+        };
+
+        let baseline_objective = evaluate(self.base);
+        let mut best = (self.base, baseline_objective);
+        for _ in 0..self.max_rounds {
+            let mut improved = false;
+            for (parameter, grid) in &self.grids {
+                for &value in grid {
+                    let candidate = parameter.set(best.0, value);
+                    if parameter.get(&candidate) == parameter.get(&best.0) || !is_valid(&candidate)
+                    {
+                        continue;
+                    }
+                    let objective = evaluate(candidate);
+                    if objective > best.1 {
+                        best = (candidate, objective);
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+
+        let (config, objective) = best;
+        let mut sensitivity: Vec<_> = self
+            .grids
+            .iter()
+            .map(|(parameter, grid)| {
+                let samples = grid
+                    .iter()
+                    .map(|&value| parameter.set(config, value))
+                    .filter(is_valid)
+                    .map(|candidate| Sample {
+                        value: parameter.get(&candidate),
+                        objective: if parameter.get(&candidate) == parameter.get(&config) {
+                            objective
+                        } else {
+                            evaluate(candidate)
+                        },
+                    })
+                    .collect();
+                Sensitivity {
+                    parameter: *parameter,
+                    value: parameter.get(&config),
+                    samples,
+                }
+            })
+            .collect();
+        sensitivity.sort_by(|a, b| b.spread().total_cmp(&a.spread()));
+
+        Tuning {
+            config,
+            objective,
+            baseline_objective,
+            trials,
+            sensitivity,
+        }
+    }
+}
+
+fn is_valid(config: &MVCompressionConfig) -> bool {
+    config.min_compression_value < config.max_compression_value
+}
+
+/// The result of [`Tuner::tune`].
+#[derive(Debug, Clone)]
+pub struct Tuning {
+    /// The best configuration found.
+    pub config: MVCompressionConfig,
+    /// Objective of the best configuration.
+    pub objective: f64,
+    /// Objective of the configuration the search started from.
+    pub baseline_objective: f64,
+    /// Number of replays run, including the sensitivity report's.
+    pub trials: usize,
+    /// How the objective responds to each tuned parameter around the best
+    /// configuration, from the most to the least sensitive.
+    pub sensitivity: Vec<Sensitivity>,
+}
+
+/// The objective along one parameter's grid, with every other parameter at
+/// its best value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensitivity {
+    /// The parameter varied.
+    pub parameter: Parameter,
+    /// Its value in the best configuration.
+    pub value: f64,
+    /// The objective at each value of the grid, in grid order.
+    pub samples: Vec<Sample>,
+}
+
+impl Sensitivity {
+    /// Returns the difference between the best and worst objective along the
+    /// grid; `0.0` for parameters that do not matter.
+    pub fn spread(&self) -> f64 {
+        let objectives = self.samples.iter().map(|sample| sample.objective);
+        let max = objectives.clone().fold(f64::NEG_INFINITY, f64::max);
+        let min = objectives.fold(f64::INFINITY, f64::min);
+        if min <= max {
+            max - min
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The objective at one parameter value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// The parameter value.
+    pub value: f64,
+    /// The objective of the configuration with that value.
+    pub objective: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bursts() -> Vec<ReplayBlock> {
+        (0..2000)
+            .map(|i| {
+                if i % 500 < 100 {
+                    ReplayBlock::new(4096, 1024)
+                } else {
+                    ReplayBlock::new(4096, 4000)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parameters_round_trip() {
+        let config = MVCompressionConfig::default();
+        for parameter in Parameter::ALL {
+            for &value in parameter.default_grid() {
+                assert_eq!(parameter.get(&parameter.set(config, value)), value);
+            }
+            let unchanged = parameter.set(config, parameter.get(&config));
+            for other in Parameter::ALL {
+                assert_eq!(
+                    other.get(&unchanged),
+                    other.get(&config),
+                    "{}",
+                    parameter.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tuning_improves_and_reports_sensitivity() {
+        let blocks = bursts();
+        let objective = net_savings(0.05);
+        let tuning = Tuner::new(objective).tune(&blocks);
+        assert!(tuning.objective > tuning.baseline_objective);
+        assert_eq!(
+            objective(&Simulator::new().run(tuning.config, blocks.iter().copied())),
+            tuning.objective
+        );
+        assert_eq!(tuning.sensitivity.len(), Parameter::ALL.len());
+        assert!(tuning
+            .sensitivity
+            .windows(2)
+            .all(|pair| pair[0].spread() >= pair[1].spread()));
+        for sensitivity in &tuning.sensitivity {
+            let best = sensitivity
+                .samples
+                .iter()
+                .map(|sample| sample.objective)
+                .fold(f64::NEG_INFINITY, f64::max);
+            assert!(best <= tuning.objective);
+        }
+
+        // The search is deterministic.
+        let again = Tuner::new(objective).tune(&blocks);
+        assert_eq!(again.objective, tuning.objective);
+        assert_eq!(again.sensitivity, tuning.sensitivity);
+    }
+
+    #[test]
+    fn test_restricted_search() {
+        let blocks = bursts();
+        let tuning = Tuner::new(|report: &ReplayReport| -(report.wasted_attempts as f64))
+            .parameters(&[Parameter::NonCompressibleBlockWeight])
+            .grid(Parameter::NonCompressibleBlockWeight, &[1.0, 32.0])
+            .max_rounds(1)
+            .tune(&blocks);
+        assert_eq!(tuning.config.non_compressible_block_weight, 32);
+        assert_eq!(tuning.sensitivity.len(), 1);
+        assert_eq!(tuning.sensitivity[0].samples.len(), 2);
+        // One baseline replay, two candidates, and one sensitivity sample that
+        // is not the best configuration.
+        assert_eq!(tuning.trials, 4);
+    }
+}
@@ -0,0 +1,264 @@
+//! Sliding-window statistics over the most recent compression outcomes.
+//!
+//! The moving averages are cheap but only report a (biased) mean and are easily
+//! dragged around by outliers. The window keeps the last few outcomes in a
+//! lock-free ring buffer so decisions can use robust statistics such as the
+//! median or the 90th percentile instead.
+
+use crate::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::ratio::{fixed_ratio, ratio_to_f32};
+
+/// Maximum number of outcomes a window can hold.
+pub const WINDOW_CAPACITY: usize = 32;
+
+/// Default number of outcomes considered by [`Statistics::window`].
+const DEFAULT_WINDOW_SIZE: usize = 16;
+
+/// Source of the statistics that drive decisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Statistics {
+    /// Exponential moving averages of block sizes (the original behavior).
+    #[default]
+    MovingAverage,
+    /// A statistic over the last `size` outcomes.
+    ///
+    /// Each block is classified as compressible or not by the chosen statistic of
+    /// the window's ratios (including the block itself), and the skip logic
+    /// compares incoming blocks to the same statistic of the window's sizes.
+    Window {
+        /// Number of outcomes considered, clamped to `1..=WINDOW_CAPACITY`.
+        size: usize,
+        /// Statistic used for decisions.
+        statistic: WindowStatistic,
+    },
+}
+
+impl Statistics {
+    /// Returns window statistics over the default window size using `statistic`.
+    pub const fn window(statistic: WindowStatistic) -> Self {
+        Statistics::Window {
+            size: DEFAULT_WINDOW_SIZE,
+            statistic,
+        }
+    }
+}
+
+/// A statistic computed over a window of outcomes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowStatistic {
+    /// Arithmetic mean.
+    Mean,
+    /// Median (nearest-rank, lower middle for even counts).
+    Median,
+    /// 90th percentile (nearest-rank).
+    P90,
+}
+
+/// Statistics over the outcomes currently in the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowStats {
+    /// Number of outcomes the statistics were computed from.
+    pub samples: usize,
+    /// Mean compression ratio.
+    pub mean_ratio: f32,
+    /// Median compression ratio.
+    pub median_ratio: f32,
+    /// 90th percentile compression ratio.
+    pub p90_ratio: f32,
+    /// Mean uncompressed block size.
+    pub mean_size: usize,
+    /// Median uncompressed block size.
+    pub median_size: usize,
+    /// 90th percentile uncompressed block size.
+    pub p90_size: usize,
+}
+
+impl WindowStats {
+    /// Returns the ratio statistic selected by `statistic`.
+    pub fn ratio(&self, statistic: WindowStatistic) -> f32 {
+        match statistic {
+            WindowStatistic::Mean => self.mean_ratio,
+            WindowStatistic::Median => self.median_ratio,
+            WindowStatistic::P90 => self.p90_ratio,
+        }
+    }
+
+    /// Returns the size statistic selected by `statistic`.
+    pub fn size(&self, statistic: WindowStatistic) -> usize {
+        match statistic {
+            WindowStatistic::Mean => self.mean_size,
+            WindowStatistic::Median => self.median_size,
+            WindowStatistic::P90 => self.p90_size,
+        }
+    }
+}
+
+/// Lock-free ring buffer of the most recent outcomes.
+///
+/// Each slot packs a fixed-point ratio (upper half) and the uncompressed size
+/// saturated to `u32::MAX` (lower half) into one atomic word, so readers never
+/// observe a torn outcome. Writers claim slots with a single `fetch_add`; a
+/// reader racing with a writer may see the slot's previous outcome, which only
+/// makes the statistics marginally stale.
+#[derive(Debug)]
+pub(crate) struct SlidingWindow {
+    slots: [AtomicU64; WINDOW_CAPACITY],
+    recorded: AtomicUsize,
+}
+
+impl SlidingWindow {
+    pub(crate) const fn new() -> Self {
+        Self {
+            slots: [const { AtomicU64::new(0) }; WINDOW_CAPACITY],
+            recorded: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records one outcome. Outcomes with an undefined ratio are ignored.
+    pub(crate) fn record(&self, compressed: usize, uncompressed: usize) {
+        let Some(ratio) = fixed_ratio(compressed, uncompressed) else {
+            return;
+        };
+        let size = uncompressed.min(u32::MAX as usize) as u64;
+        let index = self.recorded.fetch_add(1, Ordering::Relaxed) % WINDOW_CAPACITY;
+        self.slots[index].store((ratio << 32) | size, Ordering::Relaxed);
+    }
+
+    /// Forgets all outcomes. Outcomes recorded concurrently may survive.
+    pub(crate) fn clear(&self) {
+        self.recorded.store(0, Ordering::Relaxed);
+    }
+
+    /// Computes statistics over the last `size` outcomes, or `None` if empty.
+    pub(crate) fn stats(&self, size: usize) -> Option<WindowStats> {
+        let sorted = self.sorted(size)?;
+        Some(WindowStats {
+            samples: sorted.samples,
+            mean_ratio: ratio_to_f32(sorted.ratio(WindowStatistic::Mean)),
+            median_ratio: ratio_to_f32(sorted.ratio(WindowStatistic::Median)),
+            p90_ratio: ratio_to_f32(sorted.ratio(WindowStatistic::P90)),
+            mean_size: sorted.size(WindowStatistic::Mean) as usize,
+            median_size: sorted.size(WindowStatistic::Median) as usize,
+            p90_size: sorted.size(WindowStatistic::P90) as usize,
+        })
+    }
+
+    /// Returns the fixed-point ratio `statistic` over the last `size` outcomes,
+    /// or `None` if empty.
+    pub(crate) fn ratio(&self, size: usize, statistic: WindowStatistic) -> Option<u64> {
+        self.sorted(size).map(|sorted| sorted.ratio(statistic))
+    }
+
+    /// Snapshots the last `size` outcomes, sorted, or `None` if empty.
+    fn sorted(&self, size: usize) -> Option<SortedOutcomes> {
+        let recorded = self.recorded.load(Ordering::Relaxed);
+        let samples = recorded.min(size.clamp(1, WINDOW_CAPACITY));
+        if samples == 0 {
+            return None;
+        }
+
+        let mut sorted = SortedOutcomes {
+            ratios: [0; WINDOW_CAPACITY],
+            sizes: [0; WINDOW_CAPACITY],
+            samples,
+        };
+        for i in 0..samples {
+            let index = (recorded - 1 - i) % WINDOW_CAPACITY;
+            let slot = self.slots[index].load(Ordering::Relaxed);
+            sorted.ratios[i] = slot >> 32;
+            sorted.sizes[i] = slot & u32::MAX as u64;
+        }
+        sorted.ratios[..samples].sort_unstable();
+        sorted.sizes[..samples].sort_unstable();
+        Some(sorted)
+    }
+}
+
+/// A sorted snapshot of the outcomes in a window.
+struct SortedOutcomes {
+    ratios: [u64; WINDOW_CAPACITY],
+    sizes: [u64; WINDOW_CAPACITY],
+    samples: usize,
+}
+
+impl SortedOutcomes {
+    fn ratio(&self, statistic: WindowStatistic) -> u64 {
+        compute(&self.ratios[..self.samples], statistic)
+    }
+
+    fn size(&self, statistic: WindowStatistic) -> u64 {
+        compute(&self.sizes[..self.samples], statistic)
+    }
+}
+
+/// Computes `statistic` over a sorted, non-empty slice.
+fn compute(sorted: &[u64], statistic: WindowStatistic) -> u64 {
+    match statistic {
+        WindowStatistic::Mean => sorted.iter().sum::<u64>() / sorted.len() as u64,
+        WindowStatistic::Median => nearest_rank(sorted, 50),
+        WindowStatistic::P90 => nearest_rank(sorted, 90),
+    }
+}
+
+/// Returns the `percentile`-th value of a sorted, non-empty slice by nearest rank.
+fn nearest_rank(sorted: &[u64], percentile: usize) -> u64 {
+    let rank = (sorted.len() * percentile).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_window_has_no_stats() {
+        let window = SlidingWindow::new();
+        assert_eq!(window.stats(16), None);
+        window.record(10, 0);
+        assert_eq!(window.stats(16), None);
+    }
+
+    #[test]
+    fn test_window_percentiles() {
+        let window = SlidingWindow::new();
+        // Ten outcomes with ratios 0.1 ..= 1.0.
+        for i in 1..=10 {
+            window.record(i * 10, 100);
+        }
+        let stats = window.stats(10).unwrap();
+        assert_eq!(stats.samples, 10);
+        assert!((stats.mean_ratio - 0.55).abs() < 1e-4);
+        assert!((stats.median_ratio - 0.5).abs() < 1e-4);
+        assert!((stats.p90_ratio - 0.9).abs() < 1e-4);
+
+        // Ten outcomes with sizes 100 ..= 1000 replace them.
+        for i in 1..=10 {
+            window.record(0, i * 100);
+        }
+        let stats = window.stats(10).unwrap();
+        assert_eq!(stats.mean_size, 550);
+        assert_eq!(stats.median_size, 500);
+        assert_eq!(stats.p90_size, 900);
+        assert_eq!(stats.median_ratio, 0.0);
+    }
+
+    #[test]
+    fn test_window_only_keeps_recent_outcomes() {
+        let window = SlidingWindow::new();
+        for _ in 0..WINDOW_CAPACITY {
+            window.record(100, 100);
+        }
+        for _ in 0..8 {
+            window.record(25, 100);
+        }
+        let stats = window.stats(8).unwrap();
+        assert_eq!(stats.p90_ratio, 0.25);
+
+        // A request larger than the capacity sees the whole ring.
+        let stats = window.stats(usize::MAX).unwrap();
+        assert_eq!(stats.samples, WINDOW_CAPACITY);
+        assert_eq!(stats.median_ratio, 1.0);
+        assert!((stats.mean_ratio - 0.8125).abs() < 1e-4);
+    }
+}